@@ -0,0 +1,213 @@
+//! Compares resolving sidecars with the sidecar index against the old approach of generating every
+//! name google might have used and checking each one on disk.
+//!
+//! Run with `cargo bench --bench resolver`.
+
+use std::path::{Path, PathBuf};
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use g_takeout_processor::SidecarIndex;
+
+const FOLDERS: usize = 20;
+const FILES_PER_FOLDER: usize = 250;
+
+/// A takeout-shaped tree on disk, with a mix of plain, duplicate, cut short and sidecar-less media files
+struct SyntheticTree {
+    root: PathBuf,
+    media: Vec<PathBuf>,
+    sidecars: Vec<PathBuf>,
+}
+
+impl SyntheticTree {
+    fn create() -> SyntheticTree {
+        let root = std::env::temp_dir().join(format!("gdog-resolver-bench-{}", std::process::id()));
+        let mut media = Vec::new();
+        let mut sidecars = Vec::new();
+
+        for folder in 0..FOLDERS {
+            let dir = root
+                .join("Takeout/Google Photos")
+                .join(format!("Photos from {}", 2000 + folder));
+            std::fs::create_dir_all(&dir).unwrap();
+
+            for i in 0..FILES_PER_FOLDER {
+                let (media_name, json_name) = match i % 5 {
+                    0 => (
+                        format!("IMG_{:04}.jpg", i),
+                        Some(format!("IMG_{:04}.jpg.json", i)),
+                    ),
+                    1 => (
+                        format!("IMG_{:04}(1).jpg", i),
+                        Some(format!("IMG_{:04}.jpg(1).json", i)),
+                    ),
+                    // google cuts long names to 47 characters for media and 46 for sidecars
+                    2 => {
+                        let original =
+                            format!("Screenshot_20190101-12{:04}_com.google.android.apps.png", i);
+                        (
+                            format!("{}.png", &original[..43]),
+                            Some(format!("{}.json", &original[..46])),
+                        )
+                    }
+                    3 => (
+                        format!("IMG_{:04}.HEIC", i),
+                        Some(format!("IMG_{:04}.json", i)),
+                    ),
+                    _ => (format!("PXL_{:04}.jpg", i), None),
+                };
+
+                let media_path = dir.join(media_name);
+                std::fs::write(&media_path, b"").unwrap();
+                media.push(media_path);
+                if let Some(json_name) = json_name {
+                    let json_path = dir.join(json_name);
+                    std::fs::write(&json_path, b"{}").unwrap();
+                    sidecars.push(json_path);
+                }
+            }
+        }
+
+        SyntheticTree {
+            root,
+            media,
+            sidecars,
+        }
+    }
+}
+
+impl Drop for SyntheticTree {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.root);
+    }
+}
+
+fn split_duplicate_counter(file_stem: &str) -> Option<(&str, u32)> {
+    let inner = file_stem.strip_suffix(')')?;
+    let open = inner.rfind('(')?;
+    let number = inner[open + 1..].parse::<u32>().ok()?;
+    Some((&file_stem[..open], number))
+}
+
+/// The candidate generation the processor used before the sidecar index, kept here as a baseline
+fn legacy_candidates(media_path: &Path) -> Vec<PathBuf> {
+    let mut options = vec![format!("{}.json", media_path.display())];
+    let file_stem = media_path.file_stem().unwrap().to_str().unwrap();
+    let file_ext = media_path.extension().unwrap().to_str().unwrap();
+    if let Some((base, number)) = split_duplicate_counter(file_stem) {
+        let file_parent = media_path.parent().unwrap();
+        let json_filename = file_parent
+            .join(format!("{}.{}({}).json", base, file_ext, number))
+            .to_str()
+            .unwrap()
+            .to_string();
+        options.insert(0, json_filename);
+    }
+
+    if file_ext.eq_ignore_ascii_case("heic") {
+        let stripped: Vec<String> = options
+            .iter()
+            .map(|option| {
+                option
+                    .replace(".heic.json", ".json")
+                    .replace(".HEIC.json", ".json")
+            })
+            .filter(|option| !options.contains(option))
+            .collect();
+        options.splice(0..0, stripped);
+    }
+
+    for option in options.clone() {
+        for (ext, short, max) in [("jpg", "j", 1000), ("png", "p", 2000)] {
+            if !file_ext.eq_ignore_ascii_case(ext) {
+                continue;
+            }
+            for i in 1..max {
+                if option.contains(format!("({})", i).as_str()) {
+                    let base = option
+                        .replace(&format!(".{}.json", ext), "")
+                        .replace(&format!(".{}.json", ext.to_uppercase()), "");
+                    let base = base[..base.len() - i.to_string().len() - 2].to_string();
+                    options.push(format!("{}.{}({}).json", base, short, i));
+                }
+            }
+            options.push(
+                option
+                    .replace(&format!(".{}.json", ext), &format!(".{}.json", short))
+                    .replace(
+                        &format!(".{}.json", ext.to_uppercase()),
+                        &format!(".{}.json", short),
+                    ),
+            );
+        }
+    }
+
+    for option in options.clone() {
+        options.push(option.replace("-edited", ""));
+    }
+
+    for option in options.clone() {
+        options.push(option.replace("..json", ".json"));
+        options.push(option.replace("..", "."));
+    }
+
+    for option in options.clone() {
+        let file_stem = Path::new(&option).file_stem().unwrap().to_str().unwrap();
+        let file_parent = Path::new(&option).parent().unwrap();
+        for i in 0..7 {
+            if file_stem.len() <= i || file_stem.chars().rev().nth(i).unwrap() == ')' {
+                break;
+            }
+            options.push(
+                file_parent
+                    .join(format!("{}.json", &file_stem[..file_stem.len() - i]))
+                    .to_str()
+                    .unwrap()
+                    .to_string(),
+            );
+        }
+    }
+
+    options.iter().map(PathBuf::from).collect()
+}
+
+fn bench_resolver(c: &mut Criterion) {
+    let tree = SyntheticTree::create();
+
+    let mut group = c.benchmark_group(format!("resolve {} media files", tree.media.len()));
+    group.sample_size(10);
+
+    group.bench_function("candidates + Path::exists", |b| {
+        b.iter(|| {
+            let mut found = 0;
+            for media_path in &tree.media {
+                if legacy_candidates(media_path)
+                    .iter()
+                    .any(|json| json.exists())
+                {
+                    found += 1;
+                }
+            }
+            black_box(found)
+        })
+    });
+
+    group.bench_function("sidecar index", |b| {
+        b.iter(|| {
+            let mut index = SidecarIndex::default();
+            for json_path in &tree.sidecars {
+                index.insert_name(json_path);
+            }
+            let found = tree
+                .media
+                .iter()
+                .filter(|media_path| index.resolve(media_path).is_some())
+                .count();
+            black_box(found)
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_resolver);
+criterion_main!(benches);
@@ -1,21 +1,100 @@
 use std::{env, path::Path};
 
+/// remove `flag` and the value after it from the args
+fn take_flag(args: &mut Vec<String>, flag: &str) -> Option<String> {
+    let i = args.iter().position(|arg| arg == flag)?;
+    if i + 1 >= args.len() {
+        panic!("{} needs a value", flag);
+    }
+    let value = args.remove(i + 1);
+    args.remove(i);
+    Some(value)
+}
+
+/// remove `flag` from the args, returning whether it was there
+fn take_switch(args: &mut Vec<String>, flag: &str) -> bool {
+    let found = args.iter().position(|arg| arg == flag);
+    if let Some(i) = found {
+        args.remove(i);
+    }
+    found.is_some()
+}
+
 #[tokio::main]
 async fn main() {
-    // input dir is first arg, output dir is second arg
-    let args: Vec<String> = env::args().collect();
-    let input_dir = &args[1];
-    let output_dir = &args[2];
+    // input dirs/zip files are all but the last arg, output dir is the last arg
+    let mut args: Vec<String> = env::args().collect();
+
+    // `--review <file>` asks before using each fuzzy match, saving the answers to <file>
+    let review_file = take_flag(&mut args, "--review");
+    // `--config <file>` reads extra settings, see config.example.toml
+    let config_file = take_flag(&mut args, "--config");
+    // `--exif-precision <year|month|day|time>` keeps less precise guessed dates out of the exif date tags
+    let exif_precision = take_flag(&mut args, "--exif-precision");
+    // `--interpolate` estimates dates for undated files from the files around them
+    let interpolate = take_switch(&mut args, "--interpolate");
+    // `--album-output <copy|hardlink|manifest>` chooses how photos in several albums are written out
+    let album_output = take_flag(&mut args, "--album-output");
+    // `--immich-server <url>` uploads the output to immich once it is written, with the api key from
+    // `--immich-key <key>` or the IMMICH_API_KEY environment variable
+    let immich_server = take_flag(&mut args, "--immich-server");
+    let immich_key =
+        take_flag(&mut args, "--immich-key").or_else(|| env::var("IMMICH_API_KEY").ok());
+    // `--skip-existing` leaves out files the immich server already has, `--existing-checksums <file>` does the same
+    // from checksums exported from immich
+    let skip_existing = take_switch(&mut args, "--skip-existing");
+    let existing_checksums = take_flag(&mut args, "--existing-checksums");
+
+    if args.len() < 3 {
+        panic!(
+            "Usage: gdog [--review <decisions.json>] [--config <config.toml>] [--exif-precision <year|month|day|time>] [--interpolate] [--album-output <copy|hardlink|manifest>] [--immich-server <url> --immich-key <key> [--skip-existing]] [--existing-checksums <file>] <input>... <output>"
+        );
+    }
+    let inputs = &args[1..args.len() - 1];
+    let output_dir = &args[args.len() - 1];
 
     // validate input and output dirs
-    if !Path::new(input_dir).exists() {
-        panic!("Input directory does not exist");
+    for input in inputs {
+        if !Path::new(input).exists() {
+            panic!("Input `{}` does not exist", input);
+        }
     }
     if !Path::new(output_dir).exists() {
         panic!("Output directory does not exist");
     }
+    if immich_server.is_some() && immich_key.is_none() {
+        panic!("--immich-server needs an api key, from --immich-key or IMMICH_API_KEY");
+    }
+    if skip_existing && immich_server.is_none() {
+        panic!("--skip-existing needs --immich-server");
+    }
 
-    let mut processor = g_takeout_processor::Processor::new(input_dir, output_dir);
+    let mut processor = g_takeout_processor::Processor::new(&inputs[0], output_dir);
+    for input in &inputs[1..] {
+        processor.add_input(input);
+    }
+    if let Some(config_file) = config_file {
+        let config = g_takeout_processor::Config::load(Path::new(&config_file)).unwrap();
+        processor.set_config(&config).unwrap();
+    }
+    if let Some(exif_precision) = exif_precision {
+        processor.set_exif_precision(exif_precision.parse().unwrap());
+    }
+    processor.set_interpolate(interpolate);
+    if let Some(album_output) = album_output {
+        processor.set_album_output(album_output.parse().unwrap());
+    }
+    if let Some(review_file) = review_file {
+        processor.set_review_file(review_file).unwrap();
+    }
+    if let Some(existing_checksums) = existing_checksums {
+        processor
+            .skip_checksums_in(Path::new(&existing_checksums))
+            .unwrap();
+    }
+    if let (true, Some(server), Some(key)) = (skip_existing, &immich_server, &immich_key) {
+        processor.skip_assets_in_immich(server, key).await.unwrap();
+    }
 
     // load and find all photos
     processor.load_files().unwrap();
@@ -32,6 +111,14 @@ async fn main() {
     // // use exif-tool to copy exif data into the file from the source .json file
     processor.apply_exif().await.unwrap();
 
+    // put photos that are in several albums into the rest of them
+    processor.write_albums().unwrap();
+
+    // upload everything to immich, recreating the albums there
+    if let (Some(server), Some(key)) = (&immich_server, &immich_key) {
+        processor.upload_to_immich(server, key).await.unwrap();
+    }
+
     // // switch file formats where appropriate
     // processor.rename();
 
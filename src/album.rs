@@ -0,0 +1,154 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::sidecar::Timestamp;
+
+/// characters that can't be in a file name on at least one of windows, macos or linux
+const FORBIDDEN_CHARS: &[char] = &['/', '\\', ':', '*', '?', '"', '<', '>', '|'];
+
+/// names windows keeps for devices, with or without an extension
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// most filesystems allow 255 bytes, this leaves room for the ` (n)` of a duplicate title
+const MAX_FOLDER_NAME_BYTES: usize = 200;
+
+/// An album, read from the metadata file google puts in every album folder
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Album {
+    pub title: String,
+    #[serde(default)]
+    pub description: String,
+    /// when the album was made, which is often after its photos were taken
+    #[serde(default)]
+    pub date: Option<Timestamp>,
+    /// the place the album was given, if any
+    #[serde(default)]
+    pub location: Option<String>,
+    #[serde(default)]
+    pub geo_data: Option<GeoData>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct GeoData {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+impl Album {
+    /// Parse an album metadata file. Older takeouts nest the album under `albumData`.
+    pub fn parse(contents: &str) -> serde_json::Result<Album> {
+        let value: serde_json::Value = serde_json::from_str(contents)?;
+        Album::deserialize(value.get("albumData").unwrap_or(&value))
+    }
+
+    /// when the album was made, if google knows
+    pub fn date(&self) -> Option<DateTime<Utc>> {
+        self.date
+            .as_ref()?
+            .to_date()
+            .filter(|date| date.timestamp() != 0)
+    }
+
+    /// the latitude and longitude of the album, google writes zeroes when there are none
+    pub fn coordinates(&self) -> Option<(f64, f64)> {
+        let geo_data = self.geo_data?;
+        (geo_data.latitude != 0.0 || geo_data.longitude != 0.0)
+            .then_some((geo_data.latitude, geo_data.longitude))
+    }
+}
+
+/// Turn an album title into a folder name that is safe on any filesystem. Characters that aren't allowed
+/// anywhere become `_`, and everything else, apostrophes and emoji included, is kept as it is.
+pub fn folder_name(title: &str) -> String {
+    let name: String = title
+        .chars()
+        .map(|c| match c.is_control() || FORBIDDEN_CHARS.contains(&c) {
+            true => '_',
+            false => c,
+        })
+        .collect();
+
+    let mut end = name.len().min(MAX_FOLDER_NAME_BYTES);
+    while !name.is_char_boundary(end) {
+        end -= 1;
+    }
+    // a leading dot hides the folder, and windows drops trailing dots and spaces
+    let mut name = name[..end]
+        .trim()
+        .trim_start_matches('.')
+        .trim_end_matches(['.', ' '])
+        .to_owned();
+
+    let device = name.split('.').next().unwrap_or_default().to_uppercase();
+    if RESERVED_NAMES.contains(&device.as_str()) {
+        name.push('_');
+    }
+    if name.is_empty() {
+        name.push('_');
+    }
+    name
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::DateTime;
+
+    use super::{folder_name, Album};
+
+    #[test]
+    fn test_parse_album_metadata() {
+        let album = Album::parse(
+            r#"{
+                "title": "Mum's 60th 🎉",
+                "description": "At the bach",
+                "access": "protected",
+                "date": {"timestamp": "1562241600", "formatted": "4 Jul 2019, 12:00:00 UTC"},
+                "location": "Raglan",
+                "geoData": {"latitude": -37.8, "longitude": 174.87, "altitude": 0.0}
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(album.title, "Mum's 60th 🎉");
+        assert_eq!(album.description, "At the bach");
+        assert_eq!(album.date(), DateTime::from_timestamp(1_562_241_600, 0));
+        assert_eq!(album.location.as_deref(), Some("Raglan"));
+        assert_eq!(album.coordinates(), Some((-37.8, 174.87)));
+
+        let album = Album::parse(
+            r#"{"albumData": {
+                "title": "Trip",
+                "date": {"timestamp": "0", "formatted": "1 Jan 1970, 00:00:00 UTC"},
+                "geoData": {"latitude": 0.0, "longitude": 0.0}
+            }}"#,
+        )
+        .unwrap();
+        assert_eq!(album.title, "Trip");
+        assert_eq!(album.date(), None);
+        assert_eq!(album.coordinates(), None);
+    }
+
+    #[test]
+    fn test_folder_names_are_safe() {
+        for (title, expected) in [
+            ("Mum's 60th 🎉", "Mum's 60th 🎉"),
+            ("Trip: Rome/Paris", "Trip_ Rome_Paris"),
+            ("What? <Really> \"yes\" | no*", "What_ _Really_ _yes_ _ no_"),
+            ("  .hidden  ", "hidden"),
+            ("The end...", "The end"),
+            ("con", "con_"),
+            ("Aux.jpg", "Aux.jpg_"),
+            ("Console", "Console"),
+            ("line\nbreak", "line_break"),
+            ("...", "_"),
+        ] {
+            assert_eq!(folder_name(title), expected, "{:?}", title);
+        }
+
+        let long = folder_name(&"🎉".repeat(100));
+        assert!(long.len() <= 200 && long.chars().all(|c| c == '🎉'));
+    }
+}
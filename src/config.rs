@@ -0,0 +1,67 @@
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::{filename_date::DatePattern, DatePrecision};
+
+/// Settings read from a toml file, see `config.example.toml`
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// extra patterns for dates in file names, tried alongside the built in ones
+    #[serde(default)]
+    pub date_patterns: Vec<DatePatternConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DatePatternConfig {
+    /// shown in the report for every file this pattern dated
+    pub name: String,
+    /// a regex with named captures, see `DatePattern`
+    pub regex: String,
+    /// patterns are tried highest priority first, the built in ones have priority 0
+    #[serde(default)]
+    pub priority: i32,
+    pub precision: DatePrecision,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Config, Box<dyn std::error::Error>> {
+        let contents = std::fs::read_to_string(path)?;
+        toml::from_str(&contents)
+            .map_err(|e| format!("Invalid config {}: {}", path.display(), e).into())
+    }
+
+    pub fn date_patterns(&self) -> Result<Vec<DatePattern>, Box<dyn std::error::Error>> {
+        self.date_patterns
+            .iter()
+            .map(|pattern| {
+                DatePattern::custom(
+                    &pattern.name,
+                    &pattern.regex,
+                    pattern.priority,
+                    pattern.precision,
+                )
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Config;
+    use crate::DatePrecision;
+
+    #[test]
+    fn test_example_config() {
+        let config: Config = toml::from_str(include_str!("../config.example.toml")).unwrap();
+        assert_eq!(config.date_patterns.len(), 2);
+        assert_eq!(config.date_patterns[0].name, "slides");
+        assert_eq!(config.date_patterns[0].precision, DatePrecision::Month);
+
+        let patterns = config.date_patterns().unwrap();
+        assert_eq!(patterns[1].name, "dashcam");
+        assert_eq!(patterns[1].priority, 10);
+    }
+}
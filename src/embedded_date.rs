@@ -0,0 +1,821 @@
+//! Reads the date a photo or video was taken from the metadata embedded in the file itself:
+//!
+//! - JPEG: the Exif `DateTimeOriginal`, or `CreateDate` if there is no original, in the APP1 segment
+//! - HEIC: the same Exif tags, from the `Exif` item the `meta` box points at
+//! - PNG: the same Exif tags from an `eXIf` chunk, or a `Creation Time` text chunk
+//! - MP4/MOV: the QuickTime `CreateDate` in the `mvhd` box, which is in UTC
+//!
+//! `ModifyDate` is never used, since it changes whenever the file is edited. Files are read front to back, so
+//! they can be parsed straight out of an archive, and only the boxes and segments that can hold a date are kept
+//! in memory.
+
+use std::{
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+};
+
+use chrono::{DateTime, FixedOffset, Local, LocalResult, NaiveDateTime, TimeZone};
+
+use crate::filename_date::is_plausible;
+
+/// boxes and chunks bigger than this are skipped rather than read into memory
+const MAX_BLOCK_LEN: u64 = 64 * 1024 * 1024;
+
+/// seconds between the QuickTime epoch of 1904-01-01 and the unix epoch
+const QUICKTIME_EPOCH_OFFSET: i64 = 2_082_844_800;
+
+const PNG_SIGNATURE: &[u8; 8] = b"\x89PNG\r\n\x1a\n";
+
+const EXIF_IFD_POINTER: u16 = 0x8769;
+/// (date tag, offset tag, name) in order of preference
+const EXIF_DATE_TAGS: &[(u16, u16, &str)] = &[
+    (0x9003, 0x9011, "EXIF:DateTimeOriginal"),
+    (0x9004, 0x9012, "EXIF:CreateDate"),
+];
+
+/// A date read from the metadata of a file, along with the tag it came from, e.g. `EXIF:DateTimeOriginal`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmbeddedDate {
+    pub date: DateTime<Local>,
+    pub tag: &'static str,
+}
+
+/// The contents of a media file, which can be skipped through quickly when it is a real file
+pub enum MediaReader<'a> {
+    File(&'a mut File),
+    /// an entry in an archive, which has to be read to be skipped
+    Stream(&'a mut dyn Read),
+}
+
+impl Read for MediaReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            MediaReader::File(file) => file.read(buf),
+            MediaReader::Stream(reader) => reader.read(buf),
+        }
+    }
+}
+
+impl MediaReader<'_> {
+    fn skip(&mut self, len: u64) -> io::Result<()> {
+        match self {
+            // seeking past the end is fine, the next read will find the end of the file
+            MediaReader::File(file) => {
+                // no file is long enough to hold more than i64::MAX bytes, so it ends before then
+                let len = i64::try_from(len)
+                    .map_err(|_| io::Error::from(io::ErrorKind::UnexpectedEof))?;
+                file.seek(SeekFrom::Current(len)).map(drop)
+            }
+            MediaReader::Stream(reader) => {
+                match io::copy(&mut reader.take(len), &mut io::sink())? {
+                    skipped if skipped == len => Ok(()),
+                    _ => Err(io::ErrorKind::UnexpectedEof.into()),
+                }
+            }
+        }
+    }
+
+    fn read_block(&mut self, len: u64) -> io::Result<Vec<u8>> {
+        let mut block = Vec::with_capacity(len as usize);
+        self.take(len).read_to_end(&mut block)?;
+        match block.len() as u64 == len {
+            true => Ok(block),
+            false => Err(io::ErrorKind::UnexpectedEof.into()),
+        }
+    }
+}
+
+/// Read the date a file was taken from its metadata, if it is a JPEG, HEIC, PNG, MP4 or MOV file that has one.
+/// Files which end early or don't hold a plausible date give `None`.
+pub fn read(reader: &mut MediaReader) -> io::Result<Option<EmbeddedDate>> {
+    match read_any(reader) {
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+        result => result,
+    }
+}
+
+fn read_any(reader: &mut MediaReader) -> io::Result<Option<EmbeddedDate>> {
+    let mut magic = [0; 8];
+    reader.read_exact(&mut magic[..2])?;
+    if magic[..2] == [0xFF, 0xD8] {
+        return read_jpeg(reader);
+    }
+
+    reader.read_exact(&mut magic[2..])?;
+    if &magic == PNG_SIGNATURE {
+        return read_png(reader);
+    }
+    if matches!(
+        &magic[4..],
+        b"ftyp" | b"moov" | b"mdat" | b"wide" | b"free" | b"skip"
+    ) {
+        return read_bmff(reader, magic);
+    }
+
+    Ok(None)
+}
+
+fn read_u16(reader: &mut MediaReader) -> io::Result<u16> {
+    let mut buf = [0; 2];
+    reader.read_exact(&mut buf)?;
+    Ok(u16::from_be_bytes(buf))
+}
+
+fn read_u32(reader: &mut MediaReader) -> io::Result<u32> {
+    let mut buf = [0; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_be_bytes(buf))
+}
+
+/// walk the segments of a JPEG, after its start of image marker, until the Exif segment or the image data
+fn read_jpeg(reader: &mut MediaReader) -> io::Result<Option<EmbeddedDate>> {
+    loop {
+        let mut marker = [0; 2];
+        reader.read_exact(&mut marker)?;
+        if marker[0] != 0xFF {
+            return Ok(None);
+        }
+        // markers may be padded with any number of 0xFF bytes
+        let mut kind = marker[1];
+        while kind == 0xFF {
+            reader.read_exact(&mut marker[1..])?;
+            kind = marker[1];
+        }
+
+        match kind {
+            // markers without a length
+            0x01 | 0xD0..=0xD8 => continue,
+            // start of scan or end of image, the metadata always comes before these
+            0xDA | 0xD9 => return Ok(None),
+            _ => {}
+        }
+
+        let len = read_u16(reader)?.saturating_sub(2) as u64;
+        if kind != 0xE1 {
+            reader.skip(len)?;
+            continue;
+        }
+        let segment = reader.read_block(len)?;
+        if let Some(tiff) = segment.strip_prefix(b"Exif\0\0") {
+            if let Some(found) = parse_tiff(tiff) {
+                return Ok(Some(found));
+            }
+        }
+    }
+}
+
+/// walk the chunks of a PNG, after its signature, until one holds a date
+fn read_png(reader: &mut MediaReader) -> io::Result<Option<EmbeddedDate>> {
+    loop {
+        let len = read_u32(reader)? as u64;
+        let mut kind = [0; 4];
+        reader.read_exact(&mut kind)?;
+
+        let found = match &kind {
+            b"IEND" => return Ok(None),
+            b"eXIf" | b"tEXt" | b"iTXt" if len <= MAX_BLOCK_LEN => {
+                let chunk = reader.read_block(len)?;
+                match &kind {
+                    b"eXIf" => parse_tiff(chunk.strip_prefix(b"Exif\0\0").unwrap_or(&chunk)),
+                    b"tEXt" => parse_png_text(&chunk),
+                    _ => parse_png_international_text(&chunk),
+                }
+            }
+            _ => {
+                reader.skip(len)?;
+                None
+            }
+        };
+        if found.is_some() {
+            return Ok(found);
+        }
+
+        // the crc
+        reader.skip(4)?;
+    }
+}
+
+/// walk the top level boxes of an ISO base media file, which HEIC, MP4 and MOV files all are, given the header
+/// of the first box
+fn read_bmff(reader: &mut MediaReader, first: [u8; 8]) -> io::Result<Option<EmbeddedDate>> {
+    let mut position = 0u64;
+    let mut header = first;
+    loop {
+        let (size, header_len) = match u32::from_be_bytes(header[..4].try_into().unwrap()) {
+            // the box runs to the end of the file, so nothing comes after it
+            0 => return Ok(None),
+            1 => {
+                let mut large = [0; 8];
+                reader.read_exact(&mut large)?;
+                (u64::from_be_bytes(large), 16)
+            }
+            size => (size as u64, 8),
+        };
+        let Some(body_len) = size.checked_sub(header_len) else {
+            return Ok(None);
+        };
+        // where the next box starts, which a damaged size can put past the largest possible file
+        let Some(next) = position.checked_add(size) else {
+            return Ok(None);
+        };
+
+        match &header[4..] {
+            b"moov" if body_len <= MAX_BLOCK_LEN => {
+                let body = reader.read_block(body_len)?;
+                position = next;
+                if let Some(found) = parse_moov(&body) {
+                    return Ok(Some(found));
+                }
+            }
+            b"meta" if body_len <= MAX_BLOCK_LEN => {
+                let body = reader.read_block(body_len)?;
+                position = next;
+                if let Some((offset, len)) = heif_exif_location(&body) {
+                    // the exif item is usually in the `mdat` box after this one, and can't be gone back for
+                    if offset < position || len > MAX_BLOCK_LEN {
+                        return Ok(None);
+                    }
+                    reader.skip(offset - position)?;
+                    return Ok(parse_heif_exif(&reader.read_block(len)?));
+                }
+            }
+            _ => {
+                reader.skip(body_len)?;
+                position = next;
+            }
+        }
+
+        reader.read_exact(&mut header)?;
+    }
+}
+
+/// The boxes inside the body of another box
+struct Boxes<'a>(&'a [u8]);
+
+impl<'a> Iterator for Boxes<'a> {
+    type Item = (&'a [u8], &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let data = self.0;
+        let kind = data.get(4..8)?;
+        let (size, header_len) = match u32::from_be_bytes(data.get(..4)?.try_into().unwrap()) {
+            0 => (data.len() as u64, 8),
+            1 => (u64::from_be_bytes(data.get(8..16)?.try_into().unwrap()), 16),
+            size => (size as u64, 8),
+        };
+        let size = usize::try_from(size).ok()?;
+        let body = data.get(header_len..size)?;
+        self.0 = &data[size..];
+        Some((kind, body))
+    }
+}
+
+/// Big endian numbers read from the front of a slice
+struct Bytes<'a>(&'a [u8]);
+
+impl Bytes<'_> {
+    fn uint(&mut self, len: usize) -> Option<u64> {
+        let bytes = self.0.get(..len)?;
+        self.0 = &self.0[len..];
+        Some(bytes.iter().fold(0, |n, b| (n << 8) | *b as u64))
+    }
+
+    fn u16(&mut self) -> Option<u64> {
+        self.uint(2)
+    }
+
+    fn u32(&mut self) -> Option<u64> {
+        self.uint(4)
+    }
+}
+
+/// the creation time of the `mvhd` box of a movie, which is in seconds since 1904 and in UTC
+fn parse_moov(moov: &[u8]) -> Option<EmbeddedDate> {
+    let (_, mvhd) = Boxes(moov).find(|(kind, _)| *kind == b"mvhd")?;
+    let mut bytes = Bytes(mvhd);
+    let version = bytes.uint(1)?;
+    bytes.uint(3)?;
+    let seconds = match version {
+        1 => bytes.uint(8)?,
+        _ => bytes.u32()?,
+    };
+    // cameras without a clock leave it at zero
+    if seconds == 0 {
+        return None;
+    }
+
+    let date = DateTime::from_timestamp(seconds as i64 - QUICKTIME_EPOCH_OFFSET, 0)?;
+    let date = date.with_timezone(&Local);
+    is_plausible(&date).then_some(EmbeddedDate {
+        date,
+        tag: "QuickTime:CreateDate",
+    })
+}
+
+/// the absolute offset and length of the `Exif` item in a HEIF `meta` box
+fn heif_exif_location(meta: &[u8]) -> Option<(u64, u64)> {
+    // meta is a full box, with a version and flags before its children
+    let children = meta.get(4..)?;
+
+    let (_, iinf) = Boxes(children).find(|(kind, _)| *kind == b"iinf")?;
+    let entries_start = match iinf.first()? {
+        0 => 6,
+        _ => 8,
+    };
+    let exif_id = Boxes(iinf.get(entries_start..)?)
+        .filter(|(kind, _)| *kind == b"infe")
+        .find_map(|(_, infe)| {
+            let mut bytes = Bytes(infe);
+            let version = bytes.uint(1)?;
+            bytes.uint(3)?;
+            let id = match version {
+                2 => bytes.u16()?,
+                3 => bytes.u32()?,
+                _ => return None,
+            };
+            // protection index
+            bytes.u16()?;
+            (bytes.0.get(..4)? == b"Exif").then_some(id)
+        })?;
+
+    let (_, iloc) = Boxes(children).find(|(kind, _)| *kind == b"iloc")?;
+    let mut bytes = Bytes(iloc);
+    let version = bytes.uint(1)?;
+    bytes.uint(3)?;
+    let sizes = bytes.u16()?;
+    let (offset_size, length_size) = ((sizes >> 12) as usize, ((sizes >> 8) & 0xF) as usize);
+    let (base_offset_size, index_size) = (((sizes >> 4) & 0xF) as usize, (sizes & 0xF) as usize);
+    let item_count = match version {
+        0 | 1 => bytes.u16()?,
+        _ => bytes.u32()?,
+    };
+
+    for _ in 0..item_count {
+        let id = match version {
+            0 | 1 => bytes.u16()?,
+            _ => bytes.u32()?,
+        };
+        let construction_method = match version {
+            0 => 0,
+            _ => bytes.u16()? & 0xF,
+        };
+        // data reference index
+        bytes.u16()?;
+        let base_offset = bytes.uint(base_offset_size)?;
+        let extent_count = bytes.u16()?;
+
+        let mut first_extent = None;
+        for _ in 0..extent_count {
+            if version > 0 && index_size > 0 {
+                bytes.uint(index_size)?;
+            }
+            let extent = (bytes.uint(offset_size)?, bytes.uint(length_size)?);
+            first_extent.get_or_insert(extent);
+        }
+
+        if id == exif_id {
+            // only items stored in the file itself, in a single piece, can be read on the way past
+            let (offset, len) = first_extent.filter(|_| extent_count == 1)?;
+            return (construction_method == 0).then_some((base_offset + offset, len));
+        }
+    }
+
+    None
+}
+
+/// a HEIF `Exif` item starts with the offset to the tiff header
+fn parse_heif_exif(item: &[u8]) -> Option<EmbeddedDate> {
+    let mut bytes = Bytes(item);
+    let tiff_start = usize::try_from(bytes.u32()?).ok()?;
+    parse_tiff(bytes.0.get(tiff_start..)?)
+}
+
+/// A tiff structure, which is how Exif data is stored in every format
+struct Tiff<'a> {
+    data: &'a [u8],
+    big_endian: bool,
+}
+
+impl Tiff<'_> {
+    fn u16(&self, at: usize) -> Option<u16> {
+        let bytes = self.data.get(at..at + 2)?.try_into().unwrap();
+        Some(match self.big_endian {
+            true => u16::from_be_bytes(bytes),
+            false => u16::from_le_bytes(bytes),
+        })
+    }
+
+    fn u32(&self, at: usize) -> Option<u32> {
+        let bytes = self.data.get(at..at + 4)?.try_into().unwrap();
+        Some(match self.big_endian {
+            true => u32::from_be_bytes(bytes),
+            false => u32::from_le_bytes(bytes),
+        })
+    }
+
+    /// the type, count and position of the value of `tag` in the directory at `ifd`
+    fn find(&self, ifd: usize, tag: u16) -> Option<(u16, usize, usize)> {
+        let count = self.u16(ifd)? as usize;
+        (0..count).find_map(|i| {
+            let entry = ifd + 2 + i * 12;
+            if self.u16(entry)? != tag {
+                return None;
+            }
+            let kind = self.u16(entry + 2)?;
+            let count = self.u32(entry + 4)? as usize;
+            let size = match kind {
+                3 | 8 => 2,
+                4 | 9 | 11 | 13 => 4,
+                5 | 10 | 12 => 8,
+                _ => 1,
+            };
+            // values of up to four bytes are stored in the entry itself
+            let value = match count * size > 4 {
+                true => self.u32(entry + 8)? as usize,
+                false => entry + 8,
+            };
+            Some((kind, count, value))
+        })
+    }
+
+    fn find_ascii(&self, ifd: usize, tag: u16) -> Option<&str> {
+        let (kind, count, value) = self.find(ifd, tag)?;
+        if kind != 2 {
+            return None;
+        }
+        let text = std::str::from_utf8(self.data.get(value..value + count)?).ok()?;
+        Some(text.trim_end_matches(['\0', ' ']))
+    }
+}
+
+/// the first date tag of `EXIF_DATE_TAGS` found in a tiff structure, in the time zone it was taken in if known
+fn parse_tiff(data: &[u8]) -> Option<EmbeddedDate> {
+    let big_endian = match data.get(..2)? {
+        b"MM" => true,
+        b"II" => false,
+        _ => return None,
+    };
+    let tiff = Tiff { data, big_endian };
+    if tiff.u16(2)? != 42 {
+        return None;
+    }
+
+    let ifd0 = tiff.u32(4)? as usize;
+    let (_, _, pointer) = tiff.find(ifd0, EXIF_IFD_POINTER)?;
+    let exif_ifd = tiff.u32(pointer)? as usize;
+
+    EXIF_DATE_TAGS
+        .iter()
+        .find_map(|(date_tag, offset_tag, name)| {
+            let text = tiff.find_ascii(exif_ifd, *date_tag)?;
+            let date = match tiff.find_ascii(exif_ifd, *offset_tag) {
+                Some(offset) => DateTime::parse_from_str(
+                    &format!("{} {}", text, offset),
+                    "%Y:%m:%d %H:%M:%S %:z",
+                )
+                .ok()
+                .map(|date| date.with_timezone(&Local)),
+                None => local_date(text, "%Y:%m:%d %H:%M:%S"),
+            }?;
+            is_plausible(&date).then_some(EmbeddedDate { date, tag: name })
+        })
+}
+
+/// dates without a time zone are the local time of the camera
+fn local_date(text: &str, format: &str) -> Option<DateTime<Local>> {
+    let naive = NaiveDateTime::parse_from_str(text, format).ok()?;
+    match Local.from_local_datetime(&naive) {
+        LocalResult::Single(date) | LocalResult::Ambiguous(date, _) => Some(date),
+        LocalResult::None => None,
+    }
+}
+
+/// the `Creation Time` keyword, whose format isn't fixed but is meant to be RFC 1123
+fn parse_creation_time(keyword: &[u8], text: &str) -> Option<EmbeddedDate> {
+    if keyword != b"Creation Time" {
+        return None;
+    }
+
+    let text = text.trim();
+    let date = DateTime::parse_from_rfc2822(text)
+        .or_else(|_| DateTime::parse_from_rfc3339(text))
+        .map(|date: DateTime<FixedOffset>| date.with_timezone(&Local))
+        .ok()
+        .or_else(|| local_date(text, "%Y:%m:%d %H:%M:%S"))
+        .or_else(|| local_date(text, "%Y-%m-%dT%H:%M:%S"))
+        .or_else(|| local_date(text, "%Y-%m-%d %H:%M:%S"))?;
+    is_plausible(&date).then_some(EmbeddedDate {
+        date,
+        tag: "PNG:CreationTime",
+    })
+}
+
+/// `keyword\0text`
+fn parse_png_text(chunk: &[u8]) -> Option<EmbeddedDate> {
+    let split = chunk.iter().position(|b| *b == 0)?;
+    let text: String = chunk[split + 1..].iter().map(|b| *b as char).collect();
+    parse_creation_time(&chunk[..split], &text)
+}
+
+/// `keyword\0`, compression flag and method, `language\0translated keyword\0text`
+fn parse_png_international_text(chunk: &[u8]) -> Option<EmbeddedDate> {
+    let split = chunk.iter().position(|b| *b == 0)?;
+    let (keyword, rest) = (&chunk[..split], chunk.get(split + 1..)?);
+    if *rest.first()? != 0 {
+        return None;
+    }
+    let mut parts = rest.get(2..)?.splitn(3, |b| *b == 0);
+    let text = parts.nth(2)?;
+    parse_creation_time(keyword, std::str::from_utf8(text).ok()?)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::io::Cursor;
+
+    use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+
+    use super::{read, EmbeddedDate, MediaReader, PNG_SIGNATURE, QUICKTIME_EPOCH_OFFSET};
+
+    fn read_bytes(data: &[u8]) -> Option<EmbeddedDate> {
+        read(&mut MediaReader::Stream(&mut Cursor::new(data))).unwrap()
+    }
+
+    fn local(text: &str) -> DateTime<Local> {
+        let naive = NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S").unwrap();
+        Local.from_local_datetime(&naive).unwrap()
+    }
+
+    /// a tiff structure with an Exif directory holding the given ascii tags
+    fn tiff(big_endian: bool, tags: &[(u16, &str)]) -> Vec<u8> {
+        let u16 = |n: u16| match big_endian {
+            true => n.to_be_bytes(),
+            false => n.to_le_bytes(),
+        };
+        let u32 = |n: u32| match big_endian {
+            true => n.to_be_bytes(),
+            false => n.to_le_bytes(),
+        };
+
+        let mut data = match big_endian {
+            true => b"MM".to_vec(),
+            false => b"II".to_vec(),
+        };
+        data.extend(u16(42));
+        data.extend(u32(8));
+
+        // ifd0, with only the pointer to the Exif directory
+        data.extend(u16(1));
+        data.extend(u16(0x8769));
+        data.extend(u16(4));
+        data.extend(u32(1));
+        data.extend(u32(26));
+        data.extend(u32(0));
+
+        let mut values_at = 26 + 2 + tags.len() * 12 + 4;
+        let mut values = Vec::new();
+        data.extend(u16(tags.len() as u16));
+        for (tag, text) in tags {
+            let mut value = text.as_bytes().to_vec();
+            value.push(0);
+            data.extend(u16(*tag));
+            data.extend(u16(2));
+            data.extend(u32(value.len() as u32));
+            data.extend(u32(values_at as u32));
+            values_at += value.len();
+            values.extend(value);
+        }
+        data.extend(u32(0));
+        data.extend(values);
+        data
+    }
+
+    /// a JPEG with an APP0 segment, then an Exif segment holding the given tags
+    pub(crate) fn jpeg(tags: &[(u16, &str)]) -> Vec<u8> {
+        let mut data = vec![0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x10];
+        data.extend(b"JFIF\0\x01\x01\0\0\x01\0\x01\0\0");
+
+        let mut exif = b"Exif\0\0".to_vec();
+        exif.extend(tiff(false, tags));
+        data.extend([0xFF, 0xE1]);
+        data.extend((exif.len() as u16 + 2).to_be_bytes());
+        data.extend(exif);
+
+        data.extend([0xFF, 0xDA, 0x00, 0x02, 0x12, 0x34, 0xFF, 0xD9]);
+        data
+    }
+
+    fn iso_box(kind: &[u8], body: &[u8]) -> Vec<u8> {
+        let mut data = ((body.len() + 8) as u32).to_be_bytes().to_vec();
+        data.extend(kind);
+        data.extend(body);
+        data
+    }
+
+    fn mp4(creation_time: u32) -> Vec<u8> {
+        let mut mvhd = vec![0; 4];
+        mvhd.extend(creation_time.to_be_bytes());
+        mvhd.extend(creation_time.to_be_bytes());
+        mvhd.extend([0; 88]);
+
+        let mut data = iso_box(b"ftyp", b"isom\0\0\x02\0isomiso2mp41");
+        // the moov box is at the end, as it is for most phone videos
+        data.extend(iso_box(b"mdat", &[0xAB; 1000]));
+        data.extend(iso_box(b"moov", &iso_box(b"mvhd", &mvhd)));
+        data
+    }
+
+    #[test]
+    fn test_jpeg_exif_dates() {
+        for big_endian in [false, true] {
+            let mut data = vec![0xFF, 0xD8, 0xFF, 0xE1];
+            let mut exif = b"Exif\0\0".to_vec();
+            exif.extend(tiff(
+                big_endian,
+                &[
+                    (0x9004, "2019:01:02 08:00:00"),
+                    (0x9003, "2019:01:01 12:34:56"),
+                ],
+            ));
+            data.extend((exif.len() as u16 + 2).to_be_bytes());
+            data.extend(exif);
+
+            assert_eq!(
+                read_bytes(&data),
+                Some(EmbeddedDate {
+                    date: local("2019-01-01 12:34:56"),
+                    tag: "EXIF:DateTimeOriginal",
+                })
+            );
+        }
+
+        // the time zone it was taken in is kept when the camera recorded one
+        let found = read_bytes(&jpeg(&[
+            (0x9003, "2019:01:01 12:34:56"),
+            (0x9011, "+13:00"),
+        ]))
+        .unwrap();
+        assert_eq!(
+            found.date,
+            DateTime::parse_from_rfc3339("2019-01-01T12:34:56+13:00").unwrap()
+        );
+
+        let found = read_bytes(&jpeg(&[(0x9004, "2015:06:07 10:11:12")])).unwrap();
+        assert_eq!(found.tag, "EXIF:CreateDate");
+    }
+
+    #[test]
+    fn test_untrustworthy_dates_are_ignored() {
+        for data in [
+            jpeg(&[(0x9003, "0000:00:00 00:00:00")]),
+            jpeg(&[(0x9003, "    :  :     :  :  ")]),
+            jpeg(&[(0x9003, "2250:01:01 00:00:00")]),
+            // only the time it was last edited
+            jpeg(&[(0x0132, "2019:01:01 12:34:56")]),
+            jpeg(&[]),
+            mp4(0),
+            // truncated part way through
+            jpeg(&[(0x9003, "2019:01:01 12:34:56")])[..30].to_vec(),
+            b"not really a jpeg".to_vec(),
+            Vec::new(),
+        ] {
+            assert_eq!(read_bytes(&data), None);
+        }
+    }
+
+    #[test]
+    fn test_quicktime_create_date() {
+        let unix = 1_546_344_000;
+        let found = read_bytes(&mp4((unix + QUICKTIME_EPOCH_OFFSET) as u32)).unwrap();
+        assert_eq!(found.tag, "QuickTime:CreateDate");
+        assert_eq!(found.date, DateTime::from_timestamp(unix, 0).unwrap());
+
+        // a real file is skipped through rather than read
+        let path = std::env::temp_dir().join(format!("gdog-embedded-{}.mp4", std::process::id()));
+        std::fs::write(&path, mp4((unix + QUICKTIME_EPOCH_OFFSET) as u32)).unwrap();
+        let mut file = std::fs::File::open(&path).unwrap();
+        let found = read(&mut MediaReader::File(&mut file)).unwrap().unwrap();
+        assert_eq!(found.date, DateTime::from_timestamp(unix, 0).unwrap());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_oversized_boxes() {
+        let mut huge = 1u32.to_be_bytes().to_vec();
+        huge.extend(b"mdat");
+        huge.extend(u64::MAX.to_be_bytes());
+        let mut after_another = iso_box(b"ftyp", b"isom\0\0\x02\0isomiso2mp41");
+        after_another.extend(&huge);
+
+        let path = std::env::temp_dir().join(format!("gdog-oversized-{}.mp4", std::process::id()));
+        for data in [huge, after_another] {
+            assert_eq!(read_bytes(&data), None);
+
+            std::fs::write(&path, &data).unwrap();
+            let mut file = std::fs::File::open(&path).unwrap();
+            assert_eq!(read(&mut MediaReader::File(&mut file)).unwrap(), None);
+        }
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_heic_exif_item() {
+        let mut exif = 6u32.to_be_bytes().to_vec();
+        exif.extend(b"Exif\0\0");
+        exif.extend(tiff(true, &[(0x9003, "2021:03:14 09:15:30")]));
+
+        // a hvc1 image item and the Exif item
+        let mut infe_image = vec![2, 0, 0, 0, 0, 1, 0, 0];
+        infe_image.extend(b"hvc1");
+        let mut infe_exif = vec![2, 0, 0, 0, 0, 2, 0, 0];
+        infe_exif.extend(b"Exif");
+        let mut iinf = vec![0, 0, 0, 0, 0, 2];
+        iinf.extend(iso_box(b"infe", &infe_image));
+        iinf.extend(iso_box(b"infe", &infe_exif));
+
+        let ftyp = iso_box(b"ftyp", b"heic\0\0\0\0mif1heic");
+        let iloc_len = 8 + 4 + 2 + 2 + 2 * 14;
+        let meta_len = 8 + 4 + 8 + 8 + iinf.len() + iloc_len;
+        let mdat_start = (ftyp.len() + meta_len + 8) as u32;
+        let image = [0xCD; 500];
+
+        // offset and length sizes of 4, no base offset
+        let mut iloc = vec![0, 0, 0, 0, 0x44, 0x00, 0, 2];
+        for (id, offset, len) in [
+            (1u16, mdat_start, image.len() as u32),
+            (2, mdat_start + image.len() as u32, exif.len() as u32),
+        ] {
+            iloc.extend(id.to_be_bytes());
+            iloc.extend([0, 0, 0, 1]);
+            iloc.extend(offset.to_be_bytes());
+            iloc.extend(len.to_be_bytes());
+        }
+
+        let mut meta = vec![0; 4];
+        meta.extend(iso_box(b"hdlr", &[]));
+        meta.extend(iso_box(b"iinf", &iinf));
+        meta.extend(iso_box(b"iloc", &iloc));
+        let mut data = ftyp;
+        data.extend(iso_box(b"meta", &meta));
+        assert_eq!(data.len() + 8, mdat_start as usize);
+        let mut mdat = image.to_vec();
+        mdat.extend(exif);
+        data.extend(iso_box(b"mdat", &mdat));
+
+        assert_eq!(
+            read_bytes(&data),
+            Some(EmbeddedDate {
+                date: local("2021-03-14 09:15:30"),
+                tag: "EXIF:DateTimeOriginal",
+            })
+        );
+    }
+
+    #[test]
+    fn test_png_dates() {
+        let chunk = |kind: &[u8], body: &[u8]| {
+            let mut data = (body.len() as u32).to_be_bytes().to_vec();
+            data.extend(kind);
+            data.extend(body);
+            data.extend([0; 4]);
+            data
+        };
+
+        let mut png = PNG_SIGNATURE.to_vec();
+        png.extend(chunk(b"IHDR", &[0; 13]));
+        png.extend(chunk(b"tEXt", b"Software\0gdog"));
+        png.extend(chunk(
+            b"eXIf",
+            &tiff(false, &[(0x9003, "2018:05:12 14:30:00")]),
+        ));
+        png.extend(chunk(b"IEND", &[]));
+        assert_eq!(
+            read_bytes(&png).unwrap(),
+            EmbeddedDate {
+                date: local("2018-05-12 14:30:00"),
+                tag: "EXIF:DateTimeOriginal",
+            }
+        );
+
+        let mut png = PNG_SIGNATURE.to_vec();
+        png.extend(chunk(
+            b"tEXt",
+            b"Creation Time\0Sat, 12 May 2018 14:30:00 +0000",
+        ));
+        png.extend(chunk(b"IEND", &[]));
+        let found = read_bytes(&png).unwrap();
+        assert_eq!(found.tag, "PNG:CreationTime");
+        assert_eq!(
+            found.date,
+            DateTime::from_timestamp(1_526_135_400, 0).unwrap()
+        );
+
+        let mut png = PNG_SIGNATURE.to_vec();
+        png.extend(chunk(
+            b"iTXt",
+            b"Creation Time\0\0\0en\0\x002019:07:04 18:30:00",
+        ));
+        png.extend(chunk(b"IEND", &[]));
+        assert_eq!(read_bytes(&png).unwrap().date, local("2019-07-04 18:30:00"));
+    }
+}
@@ -0,0 +1,445 @@
+//! Works out what kind of file a media file really is from the first bytes of it, since google often keeps the
+//! original name of a file after converting it, e.g. a `.HEIC` that is now a jpeg. Only the start of the file is
+//! needed, so files in archives can be checked without extracting them.
+
+/// how much of a file to read to tell what it is, enough for a few transport stream packets and the first
+/// directory of a TIFF based RAW
+pub const HEADER_LEN: u64 = 4 * 1024;
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+const ASF_GUID: &[u8] = b"\x30\x26\xb2\x75\x8e\x66\xcf\x11\xa6\xd9\x00\xaa\x00\x62\xce\x6c";
+const MPEG_PACK_START: &[u8] = b"\x00\x00\x01\xba";
+const MPEG_SEQUENCE_START: &[u8] = b"\x00\x00\x01\xb3";
+
+/// the first boxes of a quicktime movie that predates the `ftyp` box
+const QUICKTIME_BOXES: &[&[u8]] = &[b"moov", b"mdat", b"wide", b"free", b"skip", b"pnot"];
+
+/// transport stream packets start with this byte
+const TS_SYNC: u8 = 0x47;
+/// how many packets in a row must line up before a file is taken to be a transport stream
+const TS_PACKETS: usize = 4;
+
+const TIFF_MAKE: u16 = 0x010f;
+const TIFF_DNG_VERSION: u16 = 0xc612;
+
+/// The format of a media file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileType {
+    Jpeg,
+    Png,
+    Gif,
+    Webp,
+    Bmp,
+    Tiff,
+    Heic,
+    /// a HEIF image that isn't HEVC or AV1 coded
+    Heif,
+    Avif,
+    Mp4,
+    Mov,
+    M4v,
+    ThreeGp,
+    Avi,
+    Asf,
+    /// an MPEG program stream, as written by DVD camcorders
+    Mpeg,
+    /// an MPEG transport stream, as written by AVCHD camcorders
+    Mts,
+    Mp3,
+    /// canon
+    Cr2,
+    Cr3,
+    Crw,
+    /// nikon
+    Nef,
+    /// sony
+    Arw,
+    /// adobe's RAW format, which some phones write too
+    Dng,
+    /// olympus
+    Orf,
+    /// panasonic
+    Rw2,
+    /// fujifilm
+    Raf,
+    /// pentax
+    Pef,
+    /// samsung
+    Srw,
+}
+
+impl FileType {
+    /// the usual extension of files of this type
+    pub fn extension(self) -> &'static str {
+        match self {
+            FileType::Jpeg => "jpg",
+            FileType::Png => "png",
+            FileType::Gif => "gif",
+            FileType::Webp => "webp",
+            FileType::Bmp => "bmp",
+            FileType::Tiff => "tiff",
+            FileType::Heic => "heic",
+            FileType::Heif => "heif",
+            FileType::Avif => "avif",
+            FileType::Mp4 => "mp4",
+            FileType::Mov => "mov",
+            FileType::M4v => "m4v",
+            FileType::ThreeGp => "3gp",
+            FileType::Avi => "avi",
+            FileType::Asf => "asf",
+            FileType::Mpeg => "mpeg",
+            FileType::Mts => "mts",
+            FileType::Mp3 => "mp3",
+            FileType::Cr2 => "cr2",
+            FileType::Cr3 => "cr3",
+            FileType::Crw => "crw",
+            FileType::Nef => "nef",
+            FileType::Arw => "arw",
+            FileType::Dng => "dng",
+            FileType::Orf => "orf",
+            FileType::Rw2 => "rw2",
+            FileType::Raf => "raf",
+            FileType::Pef => "pef",
+            FileType::Srw => "srw",
+        }
+    }
+
+    /// whether `extension` is a right one for files of this type, in any case, e.g. `JPG` and `jpeg` for a jpeg
+    pub fn matches_extension(self, extension: &str) -> bool {
+        let aliases: &[&str] = match self {
+            FileType::Jpeg => &["jpeg", "jpe"],
+            FileType::Tiff => &["tif"],
+            FileType::Heif => &["hif"],
+            FileType::Mpeg => &["mpg"],
+            FileType::Mts => &["m2ts", "ts"],
+            FileType::Asf => &["wmv"],
+            _ => &[],
+        };
+        let extension = extension.to_ascii_lowercase();
+        extension == self.extension() || aliases.contains(&extension.as_str())
+    }
+
+    /// Work out the type of a file from its first bytes, ideally `HEADER_LEN` of them. `None` if it isn't a
+    /// format photos and videos come in.
+    pub fn detect(header: &[u8]) -> Option<FileType> {
+        let at =
+            |offset: usize, magic: &[u8]| header.get(offset..offset + magic.len()) == Some(magic);
+
+        if at(0, b"\xff\xd8\xff") {
+            Some(FileType::Jpeg)
+        } else if at(0, PNG_SIGNATURE) {
+            Some(FileType::Png)
+        } else if at(0, b"GIF87a") || at(0, b"GIF89a") {
+            Some(FileType::Gif)
+        } else if at(0, b"RIFF") && at(8, b"WEBP") {
+            Some(FileType::Webp)
+        } else if at(0, b"RIFF") && at(8, b"AVI ") {
+            Some(FileType::Avi)
+        } else if at(4, b"ftyp") {
+            Some(detect_bmff(header))
+        } else if QUICKTIME_BOXES.iter().any(|kind| at(4, kind)) {
+            Some(FileType::Mov)
+        } else if at(0, b"FUJIFILMCCD-RAW") {
+            Some(FileType::Raf)
+        } else if at(0, b"II\x1a\x00\x00\x00HEAPCCDR") {
+            Some(FileType::Crw)
+        } else if at(0, b"IIRO") || at(0, b"IIRS") || at(0, b"MMOR") {
+            Some(FileType::Orf)
+        } else if at(0, b"IIU\x00") {
+            Some(FileType::Rw2)
+        } else if at(0, b"II*\x00") || at(0, b"MM\x00*") {
+            Some(detect_tiff(header))
+        } else if at(0, b"BM") && header.len() >= 26 {
+            Some(FileType::Bmp)
+        } else if at(0, ASF_GUID) {
+            Some(FileType::Asf)
+        } else if at(0, MPEG_PACK_START) || at(0, MPEG_SEQUENCE_START) {
+            Some(FileType::Mpeg)
+        } else if is_transport_stream(header, 0, 188) || is_transport_stream(header, 4, 192) {
+            Some(FileType::Mts)
+        } else if at(0, b"ID3")
+            || (header.len() >= 2 && header[0] == 0xff && header[1] & 0xe6 == 0xe2)
+        {
+            // an mpeg layer III frame header without an id3 tag in front of it
+            Some(FileType::Mp3)
+        } else {
+            None
+        }
+    }
+}
+
+/// Tell ISO base media files apart by their brands. HEIF images are `mif1` with the codec in the compatible
+/// brands, and anything that isn't known to be something else plays as an mp4.
+fn detect_bmff(header: &[u8]) -> FileType {
+    let ftyp_len = u32::from_be_bytes(header[..4].try_into().unwrap()) as usize;
+    let ftyp = header.get(..ftyp_len).unwrap_or(header);
+    let major = ftyp.get(8..12).unwrap_or_default();
+    // the minor version sits between the major brand and the compatible brands
+    let compatible: Vec<&[u8]> = ftyp.get(16..).unwrap_or_default().chunks_exact(4).collect();
+
+    match major {
+        b"heic" | b"heix" | b"heim" | b"heis" | b"hevc" | b"hevx" => FileType::Heic,
+        b"avif" | b"avis" => FileType::Avif,
+        b"mif1" | b"msf1" => {
+            if compatible
+                .iter()
+                .any(|brand| matches!(*brand, b"heic" | b"heix"))
+            {
+                FileType::Heic
+            } else if compatible.contains(&&b"avif"[..]) {
+                FileType::Avif
+            } else {
+                FileType::Heif
+            }
+        }
+        b"qt  " => FileType::Mov,
+        b"M4V " | b"M4VH" | b"M4VP" => FileType::M4v,
+        b"crx " => FileType::Cr3,
+        brand if brand.starts_with(b"3g") => FileType::ThreeGp,
+        _ => FileType::Mp4,
+    }
+}
+
+/// Most RAW formats are TIFF files underneath, so look for a RAW marker in the header and the camera make in the
+/// first directory. A TIFF from a camera that isn't known to write RAW files is a plain TIFF.
+fn detect_tiff(header: &[u8]) -> FileType {
+    if header.get(8..10) == Some(&b"CR"[..]) {
+        return FileType::Cr2;
+    }
+
+    let big_endian = header[0] == b'M';
+    let u16_at = |at: usize| {
+        let bytes = header.get(at..at + 2)?.try_into().unwrap();
+        Some(match big_endian {
+            true => u16::from_be_bytes(bytes),
+            false => u16::from_le_bytes(bytes),
+        })
+    };
+    let u32_at = |at: usize| {
+        let bytes = header.get(at..at + 4)?.try_into().unwrap();
+        Some(match big_endian {
+            true => u32::from_be_bytes(bytes),
+            false => u32::from_le_bytes(bytes),
+        } as usize)
+    };
+
+    let Some(ifd) = u32_at(4) else {
+        return FileType::Tiff;
+    };
+    let entries = u16_at(ifd).unwrap_or_default() as usize;
+    let mut make = None;
+    for entry in (0..entries).map(|i| ifd + 2 + i * 12) {
+        match u16_at(entry) {
+            Some(TIFF_DNG_VERSION) => return FileType::Dng,
+            Some(TIFF_MAKE) => {
+                let len = u32_at(entry + 4).unwrap_or_default();
+                // values of up to 4 bytes are kept in the entry itself
+                let start = match len <= 4 {
+                    true => Some(entry + 8),
+                    false => u32_at(entry + 8),
+                };
+                make = start.and_then(|start| header.get(start..start + len));
+            }
+            Some(_) => {}
+            None => break,
+        }
+    }
+
+    let make = String::from_utf8_lossy(make.unwrap_or_default()).to_ascii_uppercase();
+    match make.trim_end_matches('\0').trim() {
+        make if make.starts_with("NIKON") => FileType::Nef,
+        make if make.starts_with("SONY") => FileType::Arw,
+        make if make.starts_with("PENTAX") || make.starts_with("RICOH") => FileType::Pef,
+        make if make.starts_with("SAMSUNG") => FileType::Srw,
+        _ => FileType::Tiff,
+    }
+}
+
+/// whether `TS_PACKETS` packets of `packet_len` bytes line up, with the sync byte `offset` bytes into each
+fn is_transport_stream(header: &[u8], offset: usize, packet_len: usize) -> bool {
+    (0..TS_PACKETS).all(|i| header.get(offset + i * packet_len) == Some(&TS_SYNC))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FileType;
+
+    fn ftyp(major: &[u8], compatible: &[&[u8]]) -> Vec<u8> {
+        let mut body = major.to_vec();
+        body.extend_from_slice(&[0; 4]);
+        for brand in compatible {
+            body.extend_from_slice(brand);
+        }
+        let mut ftyp = ((body.len() + 8) as u32).to_be_bytes().to_vec();
+        ftyp.extend_from_slice(b"ftyp");
+        ftyp.extend_from_slice(&body);
+        // the next box, whose contents shouldn't be read as brands
+        ftyp.extend_from_slice(b"\x00\x00\x00\x10metaheicavif");
+        ftyp
+    }
+
+    /// a little endian TIFF with a single directory holding the given short entries and an ascii make
+    fn tiff(make: &str, tags: &[u16]) -> Vec<u8> {
+        let mut tiff = b"II*\x00\x08\x00\x00\x00".to_vec();
+        let entries = tags.len() + 1;
+        tiff.extend_from_slice(&(entries as u16).to_le_bytes());
+        let make_offset = 8 + 2 + entries * 12 + 4;
+        let mut make = make.as_bytes().to_vec();
+        make.push(0);
+        for tag in tags {
+            tiff.extend_from_slice(&tag.to_le_bytes());
+            tiff.extend_from_slice(&[3, 0, 1, 0, 0, 0, 1, 0, 0, 0]);
+        }
+        tiff.extend_from_slice(&0x010f_u16.to_le_bytes());
+        tiff.extend_from_slice(&[2, 0]);
+        tiff.extend_from_slice(&(make.len() as u32).to_le_bytes());
+        tiff.extend_from_slice(&(make_offset as u32).to_le_bytes());
+        tiff.extend_from_slice(&[0; 4]);
+        tiff.extend_from_slice(&make);
+        tiff
+    }
+
+    fn transport_stream(packet_len: usize, offset: usize) -> Vec<u8> {
+        let mut stream = vec![0; packet_len * 5];
+        for packet in stream.chunks_mut(packet_len) {
+            packet[offset] = 0x47;
+        }
+        stream
+    }
+
+    #[test]
+    fn test_detect_images() {
+        for (header, expected) in [
+            (&b"\xff\xd8\xff\xe1\x00\x10Exif"[..], FileType::Jpeg),
+            (b"\x89PNG\r\n\x1a\n\x00\x00\x00\x0dIHDR", FileType::Png),
+            (b"GIF89a\x01\x00\x01\x00", FileType::Gif),
+            (b"RIFF\x24\x00\x00\x00WEBPVP8 ", FileType::Webp),
+            (b"BM\x36\x00\x00\x00\x00\x00\x00\x00\x36\x00\x00\x00\x28\x00\x00\x00\x01\x00\x00\x00\x01\x00\x00\x00", FileType::Bmp),
+            (b"MM\x00*\x00\x00\x00\x08\x00\x00", FileType::Tiff),
+        ] {
+            assert_eq!(FileType::detect(header), Some(expected), "{:?}", expected);
+        }
+
+        assert_eq!(
+            FileType::detect(&ftyp(b"heic", &[b"mif1", b"heic"])),
+            Some(FileType::Heic)
+        );
+        assert_eq!(
+            FileType::detect(&ftyp(b"mif1", &[b"mif1", b"heic"])),
+            Some(FileType::Heic)
+        );
+        assert_eq!(
+            FileType::detect(&ftyp(b"avif", &[b"mif1", b"avif"])),
+            Some(FileType::Avif)
+        );
+        assert_eq!(
+            FileType::detect(&ftyp(b"mif1", &[b"mif1", b"avif"])),
+            Some(FileType::Avif)
+        );
+        // the brands in the box after `ftyp` don't count
+        assert_eq!(
+            FileType::detect(&ftyp(b"mif1", &[b"mif1"])),
+            Some(FileType::Heif)
+        );
+    }
+
+    #[test]
+    fn test_detect_videos() {
+        for (major, expected) in [
+            (b"isom", FileType::Mp4),
+            (b"mp42", FileType::Mp4),
+            (b"qt  ", FileType::Mov),
+            (b"M4V ", FileType::M4v),
+            (b"3gp4", FileType::ThreeGp),
+            (b"3g2a", FileType::ThreeGp),
+        ] {
+            assert_eq!(
+                FileType::detect(&ftyp(major, &[b"isom"])),
+                Some(expected),
+                "{:?}",
+                expected
+            );
+        }
+
+        for (header, expected) in [
+            (
+                &b"\x00\x00\x00\x08wide\x00\x00\x00\x00mdat"[..],
+                FileType::Mov,
+            ),
+            (b"RIFF\x24\x00\x00\x00AVI LIST", FileType::Avi),
+            (
+                b"\x30\x26\xb2\x75\x8e\x66\xcf\x11\xa6\xd9\x00\xaa\x00\x62\xce\x6c",
+                FileType::Asf,
+            ),
+            (b"\x00\x00\x01\xba\x44\x00\x04\x00", FileType::Mpeg),
+            (b"ID3\x04\x00\x00\x00\x00\x00\x00", FileType::Mp3),
+        ] {
+            assert_eq!(FileType::detect(header), Some(expected), "{:?}", expected);
+        }
+
+        assert_eq!(
+            FileType::detect(&transport_stream(188, 0)),
+            Some(FileType::Mts)
+        );
+        assert_eq!(
+            FileType::detect(&transport_stream(192, 4)),
+            Some(FileType::Mts)
+        );
+        // a single packet's worth isn't enough to be sure
+        assert_eq!(FileType::detect(&transport_stream(192, 4)[..200]), None);
+    }
+
+    #[test]
+    fn test_detect_raw() {
+        for (header, expected) in [
+            (&b"II*\x00\x10\x00\x00\x00CR\x02\x00"[..], FileType::Cr2),
+            (b"II\x1a\x00\x00\x00HEAPCCDR", FileType::Crw),
+            (b"FUJIFILMCCD-RAW 0201", FileType::Raf),
+            (b"IIRO\x08\x00\x00\x00", FileType::Orf),
+            (b"IIU\x00\x18\x00\x00\x00", FileType::Rw2),
+        ] {
+            assert_eq!(FileType::detect(header), Some(expected), "{:?}", expected);
+        }
+        assert_eq!(
+            FileType::detect(&ftyp(b"crx ", &[b"crx ", b"isom"])),
+            Some(FileType::Cr3)
+        );
+
+        assert_eq!(
+            FileType::detect(&tiff("NIKON CORPORATION", &[0x00fe])),
+            Some(FileType::Nef)
+        );
+        assert_eq!(FileType::detect(&tiff("SONY", &[])), Some(FileType::Arw));
+        assert_eq!(FileType::detect(&tiff("PENTAX", &[])), Some(FileType::Pef));
+        assert_eq!(
+            FileType::detect(&tiff("Google", &[0xc612])),
+            Some(FileType::Dng)
+        );
+        assert_eq!(
+            FileType::detect(&tiff("Epson scanner", &[])),
+            Some(FileType::Tiff)
+        );
+    }
+
+    #[test]
+    fn test_matching_extensions() {
+        assert!(FileType::Mts.matches_extension("MTS"));
+        assert!(FileType::Jpeg.matches_extension("JPG"));
+        assert!(FileType::Jpeg.matches_extension("jpeg"));
+        assert!(FileType::Asf.matches_extension("wmv"));
+        assert!(!FileType::Jpeg.matches_extension("HEIC"));
+        assert!(!FileType::Mp4.matches_extension("mov"));
+    }
+
+    #[test]
+    fn test_unknown_files() {
+        for header in [
+            &b""[..],
+            b"not really a jpeg",
+            b"\xff",
+            b"RIFF\x24\x00\x00\x00WAVEfmt ",
+        ] {
+            assert_eq!(FileType::detect(header), None, "{:?}", header);
+        }
+    }
+}
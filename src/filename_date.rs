@@ -0,0 +1,421 @@
+use std::sync::LazyLock;
+
+use chrono::{DateTime, Datelike, Local, LocalResult, NaiveDate, NaiveTime, TimeZone};
+use regex::Regex;
+
+use crate::DatePrecision;
+
+/// A date read from a file name, along with the pattern that found it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilenameDate {
+    pub date: DateTime<Local>,
+    pub precision: DatePrecision,
+    pub pattern: String,
+}
+
+/// A named regex for dates in file names. The date is taken from the named captures `year`, `month`, `day`,
+/// `hour`, `minute`, `second` and `millis`, or `unix` and `unix_millis` for epoch timestamps. Only `year` is
+/// required, the precision of the date depends on which captures are present unless the pattern sets one.
+#[derive(Debug, Clone)]
+pub struct DatePattern {
+    pub name: String,
+    regex: Regex,
+    /// patterns are tried highest priority first, the built in ones have priority 0
+    pub priority: i32,
+    precision: Option<DatePrecision>,
+}
+
+/// the captures a pattern needs to give a date of each precision
+const REQUIRED_CAPTURES: &[(DatePrecision, &[&str])] = &[
+    (DatePrecision::Year, &["year"]),
+    (DatePrecision::Month, &["year", "month"]),
+    (DatePrecision::Day, &["year", "month", "day"]),
+    (DatePrecision::Time, &["year", "month", "day", "hour"]),
+];
+
+/// digits that aren't part of a longer number
+const BEFORE: &str = "(?:^|[^0-9])";
+const AFTER: &str = "(?:[^0-9]|$)";
+const YEAR: &str = "(?P<year>(?:19|20)[0-9]{2})";
+
+static BUILTIN_PATTERNS: LazyLock<Vec<DatePattern>> = LazyLock::new(|| {
+    let date = format!(
+        "{}[-_.]?(?P<month>[0-9]{{2}})[-_.]?(?P<day>[0-9]{{2}})",
+        YEAR
+    );
+    let time = "(?P<hour>[0-9]{2})[-_.:]?(?P<minute>[0-9]{2})[-_.:]?(?P<second>[0-9]{2})";
+    [
+        // IMG_20190101_123456, PXL_20210314_091530123, Screenshot_2019-01-01-12-34-56, signal-2020-05-06-101010
+        (
+            "date_time",
+            format!(
+                "{}{}[-_. T]?{}(?P<millis>[0-9]{{3}})?{}",
+                BEFORE, date, time, AFTER
+            ),
+        ),
+        // IMG-20190101-WA0001, 2019-01-01
+        ("date", format!("{}{}{}", BEFORE, date, AFTER)),
+        // FB_IMG_1552945417291
+        (
+            "unix_millis",
+            format!("{}(?P<unix_millis>1[0-9]{{12}}){}", BEFORE, AFTER),
+        ),
+        // 1546344000
+        ("unix", format!("{}(?P<unix>1[0-9]{{9}}){}", BEFORE, AFTER)),
+    ]
+    .into_iter()
+    .map(|(name, regex)| DatePattern::new(name, &regex).unwrap())
+    .collect()
+});
+
+/// dates in the future are more likely to be some other number which happens to look like one
+pub(crate) fn is_plausible(date: &DateTime<Local>) -> bool {
+    (1970..=Local::now().year() + 1).contains(&date.year())
+}
+
+impl DatePattern {
+    pub fn new(name: &str, regex: &str) -> Result<DatePattern, regex::Error> {
+        Ok(DatePattern {
+            name: name.to_owned(),
+            regex: Regex::new(regex)?,
+            priority: 0,
+            precision: None,
+        })
+    }
+
+    /// A pattern from the config, which always gives dates of the given precision. Finer parts of the date
+    /// that are captured anyway are dropped.
+    pub fn custom(
+        name: &str,
+        regex: &str,
+        priority: i32,
+        precision: DatePrecision,
+    ) -> Result<DatePattern, Box<dyn std::error::Error>> {
+        let mut pattern = DatePattern::new(name, regex)
+            .map_err(|e| format!("Invalid regex for date pattern `{}`: {}", name, e))?;
+
+        let names: Vec<&str> = pattern.regex.capture_names().flatten().collect();
+        let is_epoch = names.contains(&"unix") || names.contains(&"unix_millis");
+        let (_, required) = REQUIRED_CAPTURES
+            .iter()
+            .find(|(required_precision, _)| *required_precision == precision)
+            .unwrap();
+        if !is_epoch {
+            if let Some(missing) = required.iter().find(|capture| !names.contains(capture)) {
+                return Err(format!(
+                    "Date pattern `{}` needs a `{}` capture to give {:?} precision",
+                    name, missing, precision
+                )
+                .into());
+            }
+        }
+
+        pattern.priority = priority;
+        pattern.precision = Some(precision);
+        Ok(pattern)
+    }
+
+    /// find the first valid date this pattern matches in `text`
+    pub fn find(&self, text: &str) -> Option<FilenameDate> {
+        self.regex.captures_iter(text).find_map(|captures| {
+            self.date_from(&captures)
+                .filter(|found| is_plausible(&found.date))
+        })
+    }
+
+    fn date_from(&self, captures: &regex::Captures) -> Option<FilenameDate> {
+        let number = |name: &str| captures.name(name).map(|m| m.as_str().parse::<i64>());
+        let found = |date: DateTime<Local>, precision| {
+            Some(FilenameDate {
+                date,
+                precision,
+                pattern: self.name.clone(),
+            })
+        };
+
+        // epoch timestamps are in UTC
+        if let Some(millis) = number("unix_millis") {
+            let date = DateTime::from_timestamp_millis(millis.ok()?)?;
+            return found(date.with_timezone(&Local), DatePrecision::Time);
+        }
+        if let Some(seconds) = number("unix") {
+            let date = DateTime::from_timestamp(seconds.ok()?, 0)?;
+            return found(date.with_timezone(&Local), DatePrecision::Time);
+        }
+
+        let year = number("year")?.ok()? as i32;
+        let mut month = number("month").transpose().ok()?;
+        let mut day = number("day").transpose().ok()?;
+        let mut hour = number("hour").transpose().ok()?;
+        let mut minute = number("minute").transpose().ok()?;
+        let mut second = number("second").transpose().ok()?;
+        let mut millis = number("millis").transpose().ok()?;
+        let precision = self.precision.unwrap_or(match (month, day, hour) {
+            (Some(_), Some(_), Some(_)) => DatePrecision::Time,
+            (Some(_), Some(_), None) => DatePrecision::Day,
+            (Some(_), None, _) => DatePrecision::Month,
+            _ => DatePrecision::Year,
+        });
+
+        // drop whatever is finer than the precision, so the date is the start of the range it stands for
+        if precision < DatePrecision::Time {
+            (hour, minute, second, millis) = (None, None, None, None);
+        }
+        if precision < DatePrecision::Day {
+            day = None;
+        }
+        if precision < DatePrecision::Month {
+            month = None;
+        }
+
+        let date =
+            NaiveDate::from_ymd_opt(year, month.unwrap_or(1) as u32, day.unwrap_or(1) as u32)?;
+        let time = NaiveTime::from_hms_milli_opt(
+            hour.unwrap_or(0) as u32,
+            minute.unwrap_or(0) as u32,
+            second.unwrap_or(0) as u32,
+            millis.unwrap_or(0) as u32,
+        )?;
+
+        // times in file names are the local time of the camera
+        match Local.from_local_datetime(&date.and_time(time)) {
+            LocalResult::Single(date) | LocalResult::Ambiguous(date, _) => found(date, precision),
+            LocalResult::None => None,
+        }
+    }
+}
+
+/// the built in patterns followed or preceded by `custom` ones depending on their priority, custom patterns win
+/// ties with built in ones
+pub fn with_custom(custom: Vec<DatePattern>) -> Vec<DatePattern> {
+    let mut patterns = custom;
+    patterns.extend(BUILTIN_PATTERNS.iter().cloned());
+    patterns.sort_by_key(|pattern| std::cmp::Reverse(pattern.priority));
+    patterns
+}
+
+pub fn builtin_patterns() -> Vec<DatePattern> {
+    BUILTIN_PATTERNS.clone()
+}
+
+/// Read the date a file was taken from its name with the first of `patterns` to find one, keeping the time of
+/// day if the name has one, e.g. `IMG_20190101_123456`, `Screenshot_2019-01-01-12-34-56`, `IMG-20190101-WA0001`
+/// or `1546344000`
+pub fn parse_with(patterns: &[DatePattern], file_stem: &str) -> Option<FilenameDate> {
+    patterns.iter().find_map(|pattern| pattern.find(file_stem))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, Local, NaiveDateTime, TimeZone, Timelike};
+
+    use super::{builtin_patterns, parse_with, with_custom, DatePattern, FilenameDate};
+    use crate::DatePrecision;
+
+    fn parse(file_stem: &str) -> Option<FilenameDate> {
+        parse_with(&builtin_patterns(), file_stem)
+    }
+
+    /// (file stem, expected local time, precision, pattern)
+    const CORPUS: &[(&str, &str, DatePrecision, &str)] = &[
+        (
+            "IMG_20190101_123456",
+            "2019-01-01 12:34:56",
+            DatePrecision::Time,
+            "date_time",
+        ),
+        (
+            "VID_20190101_123456~2",
+            "2019-01-01 12:34:56",
+            DatePrecision::Time,
+            "date_time",
+        ),
+        (
+            "PXL_20210314_091530123",
+            "2021-03-14 09:15:30",
+            DatePrecision::Time,
+            "date_time",
+        ),
+        (
+            "PXL_20210314_091530123.PORTRAIT",
+            "2021-03-14 09:15:30",
+            DatePrecision::Time,
+            "date_time",
+        ),
+        (
+            "Screenshot_2019-01-01-12-34-56",
+            "2019-01-01 12:34:56",
+            DatePrecision::Time,
+            "date_time",
+        ),
+        (
+            "Screenshot_20190101-123456_Chrome",
+            "2019-01-01 12:34:56",
+            DatePrecision::Time,
+            "date_time",
+        ),
+        (
+            "signal-2020-05-06-101010",
+            "2020-05-06 10:10:10",
+            DatePrecision::Time,
+            "date_time",
+        ),
+        (
+            "2019-07-04 18.30.00",
+            "2019-07-04 18:30:00",
+            DatePrecision::Time,
+            "date_time",
+        ),
+        (
+            "20180512_143000(1)",
+            "2018-05-12 14:30:00",
+            DatePrecision::Time,
+            "date_time",
+        ),
+        (
+            "IMG-20190101-WA0001",
+            "2019-01-01 00:00:00",
+            DatePrecision::Day,
+            "date",
+        ),
+        (
+            "VID-20161224-WA0012",
+            "2016-12-24 00:00:00",
+            DatePrecision::Day,
+            "date",
+        ),
+        (
+            "2019-07-04",
+            "2019-07-04 00:00:00",
+            DatePrecision::Day,
+            "date",
+        ),
+        (
+            "Scan 2001_09_11",
+            "2001-09-11 00:00:00",
+            DatePrecision::Day,
+            "date",
+        ),
+        (
+            "IMG_20151120_edited",
+            "2015-11-20 00:00:00",
+            DatePrecision::Day,
+            "date",
+        ),
+    ];
+
+    /// (file stem, unix time in milliseconds)
+    const EPOCH_CORPUS: &[(&str, i64)] = &[
+        ("1546344000", 1_546_344_000_000),
+        ("received_1546344000", 1_546_344_000_000),
+        ("FB_IMG_1552945417291", 1_552_945_417_291),
+        ("1552945417291", 1_552_945_417_291),
+    ];
+
+    const NO_DATE: &[&str] = &[
+        "IMG_1234",
+        "DSC00042",
+        "Untitled",
+        "IMG_99999999",
+        "IMG_20191340",
+        "IMG_30190101",
+        "MVIMG_123456789012",
+        "original_4b1e0c3a-8f2d-4a1b-9c3e-7d6f5a4b3c2d_P",
+    ];
+
+    #[test]
+    fn test_filename_corpus() {
+        for (file_stem, expected, precision, pattern) in CORPUS {
+            let expected = NaiveDateTime::parse_from_str(expected, "%Y-%m-%d %H:%M:%S").unwrap();
+            let expected = Local.from_local_datetime(&expected).unwrap();
+
+            let found = parse(file_stem).unwrap_or_else(|| panic!("no date in {}", file_stem));
+            assert_eq!(
+                found.date.with_nanosecond(0).unwrap(),
+                expected,
+                "{}",
+                file_stem
+            );
+            assert_eq!(found.precision, *precision, "{}", file_stem);
+            assert_eq!(found.pattern, *pattern, "{}", file_stem);
+        }
+    }
+
+    #[test]
+    fn test_epoch_corpus() {
+        for (file_stem, millis) in EPOCH_CORPUS {
+            let found = parse(file_stem).unwrap_or_else(|| panic!("no date in {}", file_stem));
+            assert_eq!(
+                found.date,
+                DateTime::from_timestamp_millis(*millis).unwrap(),
+                "{}",
+                file_stem
+            );
+            assert_eq!(found.precision, DatePrecision::Time);
+        }
+    }
+
+    #[test]
+    fn test_names_without_dates() {
+        for file_stem in NO_DATE {
+            assert_eq!(parse(file_stem), None, "{}", file_stem);
+        }
+    }
+
+    #[test]
+    fn test_custom_patterns() {
+        let slides = DatePattern::custom(
+            "slides",
+            r"Slides_(?P<year>[0-9]{4})-(?P<month>[0-9]{2})_roll",
+            10,
+            DatePrecision::Month,
+        )
+        .unwrap();
+        // captures the day, but is only trusted to the month
+        let dashcam = DatePattern::custom(
+            "dashcam",
+            r"^(?P<year>[0-9]{4})(?P<month>[0-9]{2})(?P<day>[0-9]{2})_[0-9]{6}_[FR]$",
+            5,
+            DatePrecision::Day,
+        )
+        .unwrap();
+        let patterns = with_custom(vec![dashcam, slides]);
+        assert_eq!(patterns[0].name, "slides");
+        assert_eq!(patterns[1].name, "dashcam");
+
+        let found = parse_with(&patterns, "Slides_1987-06_roll12_004").unwrap();
+        assert_eq!(found.pattern, "slides");
+        assert_eq!(found.precision, DatePrecision::Month);
+        assert_eq!(
+            found.date.naive_local(),
+            NaiveDateTime::parse_from_str("1987-06-01 00:00:00", "%Y-%m-%d %H:%M:%S").unwrap()
+        );
+
+        let found = parse_with(&patterns, "20230101_120000_F").unwrap();
+        assert_eq!(found.pattern, "dashcam");
+        assert_eq!(found.precision, DatePrecision::Day);
+        assert_eq!(
+            found.date.naive_local(),
+            NaiveDateTime::parse_from_str("2023-01-01 00:00:00", "%Y-%m-%d %H:%M:%S").unwrap()
+        );
+
+        // everything else still falls through to the built in patterns
+        assert_eq!(
+            parse_with(&patterns, "IMG_20190101_123456")
+                .unwrap()
+                .pattern,
+            "date_time"
+        );
+    }
+
+    #[test]
+    fn test_custom_pattern_needs_captures_for_its_precision() {
+        let error =
+            DatePattern::custom("roll", r"(?P<year>[0-9]{4})_roll", 0, DatePrecision::Month)
+                .unwrap_err();
+        assert!(error.to_string().contains("`month`"));
+        assert!(
+            DatePattern::custom("broken", r"(?P<year>[0-9]{4}", 0, DatePrecision::Year).is_err()
+        );
+    }
+}
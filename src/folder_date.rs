@@ -0,0 +1,255 @@
+use std::sync::LazyLock;
+
+use chrono::{Datelike, Local, NaiveDate};
+use regex::Regex;
+
+use crate::DatePrecision;
+
+/// A date read from a folder name, which is usually a range rather than a single day
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FolderDate {
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+    pub precision: DatePrecision,
+}
+
+const MONTHS: &[&str] = &[
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+
+/// (name, first month, number of months), winter runs into the next year. These are the northern hemisphere's
+/// seasons, south of the equator they are the other way round, so only the year of a season is trusted.
+const SEASONS: &[(&str, u32, u32)] = &[
+    ("spring", 3, 3),
+    ("summer", 6, 3),
+    ("autumn", 9, 3),
+    ("fall", 9, 3),
+    ("winter", 12, 3),
+];
+
+/// `2019-07-04`, `2019_07_04`, `2019.07.04`, `20190704`
+static DAY: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?:^|[^0-9])((?:19|20)[0-9]{2})[-_. ]?([0-9]{2})[-_. ]?([0-9]{2})(?:[^0-9]|$)")
+        .unwrap()
+});
+
+/// `Dec 2012`, `December 2012`, `2012 December`
+static MONTH_NAME: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"(?i)\b(?:([a-z]{3,9})[ ,]+((?:19|20)[0-9]{2})|((?:19|20)[0-9]{2})[ ,]+([a-z]{3,9}))\b",
+    )
+    .unwrap()
+});
+
+/// `2012-12`, `2012_12`
+static MONTH: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?:^|[^0-9])((?:19|20)[0-9]{2})[-_.]([0-9]{2})(?:[^0-9]|$)").unwrap()
+});
+
+/// `Photos from 2014`, `Summer 2016`, `2016`
+static YEAR: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?:^|[^0-9])((?:19|20)[0-9]{2})(?:[^0-9]|$)").unwrap());
+
+/// the month named by `name`, which can be abbreviated down to three letters
+fn month_number(name: &str) -> Option<u32> {
+    let name = name.to_lowercase();
+    let full = [
+        "january",
+        "february",
+        "march",
+        "april",
+        "may",
+        "june",
+        "july",
+        "august",
+        "september",
+        "october",
+        "november",
+        "december",
+    ];
+    MONTHS
+        .iter()
+        .zip(full)
+        .position(|(short, full)| name.starts_with(short) && full.starts_with(name.as_str()))
+        .map(|i| i as u32 + 1)
+}
+
+/// the last day of the month `months` months after the first of `year`-`month`
+fn end_of_months(year: i32, month: u32, months: u32) -> Option<NaiveDate> {
+    let next = month - 1 + months;
+    let (year, month) = (year + (next / 12) as i32, next % 12 + 1);
+    NaiveDate::from_ymd_opt(year, month, 1)?.pred_opt()
+}
+
+fn month_range(year: i32, month: u32, months: u32, precision: DatePrecision) -> Option<FolderDate> {
+    Some(FolderDate {
+        start: NaiveDate::from_ymd_opt(year, month, 1)?,
+        end: end_of_months(year, month, months)?,
+        precision,
+    })
+}
+
+/// years in the future are more likely to be a camera model or some other number
+fn is_plausible_year(year: i32) -> bool {
+    year <= Local::now().year() + 1
+}
+
+/// Read the date a folder of photos was taken from its name, e.g. "Photos from 2014", "2019-07-04 Beach trip",
+/// "Summer 2016" or "Dec 2012". The most precise date in the name wins.
+pub fn parse(folder_name: &str) -> Option<FolderDate> {
+    for captures in DAY.captures_iter(folder_name) {
+        let year = captures[1].parse().ok()?;
+        let date =
+            NaiveDate::from_ymd_opt(year, captures[2].parse().ok()?, captures[3].parse().ok()?);
+        if let Some(date) = date.filter(|_| is_plausible_year(year)) {
+            return Some(FolderDate {
+                start: date,
+                end: date,
+                precision: DatePrecision::Day,
+            });
+        }
+    }
+
+    for captures in MONTH_NAME.captures_iter(folder_name) {
+        let (name, year) = match (captures.get(1), captures.get(2)) {
+            (Some(name), Some(year)) => (name.as_str(), year.as_str()),
+            _ => (&captures[4], &captures[3]),
+        };
+        let year = year.parse().ok()?;
+        if !is_plausible_year(year) {
+            continue;
+        }
+
+        if let Some(month) = month_number(name) {
+            return month_range(year, month, 1, DatePrecision::Month);
+        }
+        let name = name.to_lowercase();
+        if let Some((_, month, months)) = SEASONS.iter().find(|(season, _, _)| *season == name) {
+            return month_range(year, *month, *months, DatePrecision::Year);
+        }
+    }
+
+    for captures in MONTH.captures_iter(folder_name) {
+        let year = captures[1].parse().ok()?;
+        let month = captures[2].parse().ok()?;
+        if is_plausible_year(year) && (1..=12).contains(&month) {
+            return month_range(year, month, 1, DatePrecision::Month);
+        }
+    }
+
+    for captures in YEAR.captures_iter(folder_name) {
+        let year = captures[1].parse().ok()?;
+        if is_plausible_year(year) {
+            return month_range(year, 1, 12, DatePrecision::Year);
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::parse;
+    use crate::DatePrecision;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    #[test]
+    fn test_parse_folder_dates() {
+        let cases = [
+            (
+                "Photos from 2014",
+                date(2014, 1, 1),
+                date(2014, 12, 31),
+                DatePrecision::Year,
+            ),
+            (
+                "Fotos von 2014",
+                date(2014, 1, 1),
+                date(2014, 12, 31),
+                DatePrecision::Year,
+            ),
+            (
+                "2019-07-04 Beach trip",
+                date(2019, 7, 4),
+                date(2019, 7, 4),
+                DatePrecision::Day,
+            ),
+            (
+                "Wedding 20180512",
+                date(2018, 5, 12),
+                date(2018, 5, 12),
+                DatePrecision::Day,
+            ),
+            (
+                "Summer 2016",
+                date(2016, 6, 1),
+                date(2016, 8, 31),
+                DatePrecision::Year,
+            ),
+            (
+                "Fall 2018",
+                date(2018, 9, 1),
+                date(2018, 11, 30),
+                DatePrecision::Year,
+            ),
+            (
+                "Winter 2016",
+                date(2016, 12, 1),
+                date(2017, 2, 28),
+                DatePrecision::Year,
+            ),
+            (
+                "Dec 2012",
+                date(2012, 12, 1),
+                date(2012, 12, 31),
+                DatePrecision::Month,
+            ),
+            (
+                "Christmas, December 2012",
+                date(2012, 12, 1),
+                date(2012, 12, 31),
+                DatePrecision::Month,
+            ),
+            (
+                "2020 February",
+                date(2020, 2, 1),
+                date(2020, 2, 29),
+                DatePrecision::Month,
+            ),
+            (
+                "2015-03 Skiing",
+                date(2015, 3, 1),
+                date(2015, 3, 31),
+                DatePrecision::Month,
+            ),
+        ];
+
+        for (folder_name, start, end, precision) in cases {
+            let parsed = parse(folder_name).unwrap_or_else(|| panic!("{}", folder_name));
+            assert_eq!(
+                (parsed.start, parsed.end, parsed.precision),
+                (start, end, precision),
+                "{}",
+                folder_name
+            );
+        }
+    }
+
+    #[test]
+    fn test_folders_without_dates() {
+        for folder_name in [
+            "Beach trip",
+            "Untitled(2)",
+            "Canon EOS 5000",
+            "Archive",
+            "Mum's 60th",
+        ] {
+            assert_eq!(parse(folder_name), None, "{}", folder_name);
+        }
+    }
+}
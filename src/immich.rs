@@ -0,0 +1,872 @@
+//! Uploads the output to an Immich server through its REST API, and recreates the albums on it.
+//!
+//! Every file is sent with a device asset id and the SHA-1 checksum Immich keeps of each asset, so the server
+//! recognises files it already has instead of storing them twice. What has been uploaded is saved to a state file
+//! as it goes, so an interrupted upload carries on where it stopped when run again.
+
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io,
+    path::{Path, PathBuf},
+};
+
+use base64::{prelude::BASE64_STANDARD, Engine};
+use chrono::{DateTime, Utc};
+use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
+use reqwest::multipart::{Form, Part};
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+
+/// the device Immich records the uploads as coming from
+const DEVICE_ID: &str = "gdog";
+/// how many files are uploaded at once
+const CONCURRENT_UPLOADS: usize = 8;
+/// how many assets are sent in one request when adding them to an album or marking them
+const BATCH_SIZE: usize = 500;
+/// how many assets are fetched at a time when listing what is already on the server
+const SEARCH_PAGE_SIZE: usize = 1000;
+
+/// A file to upload
+#[derive(Debug, Clone)]
+pub struct Upload {
+    /// where the file is on disk
+    pub path: PathBuf,
+    /// what the file is called in the state file and in albums, its path relative to the output directory
+    pub key: PathBuf,
+    /// when the photo was taken, the file's modification time is used if this isn't known
+    pub taken_at: Option<DateTime<Utc>>,
+    pub favorite: bool,
+    pub archived: bool,
+}
+
+/// An album to recreate, with its files in order by the keys of their uploads
+#[derive(Debug, Clone)]
+pub struct UploadAlbum {
+    /// the output folder name of the album, which unlike its title is unique
+    pub name: String,
+    pub title: String,
+    pub description: String,
+    /// whether the album was shared with the user by someone else
+    pub shared: bool,
+    pub files: Vec<PathBuf>,
+    /// the ids of assets in the album that were already on the server, so weren't uploaded
+    pub existing: Vec<String>,
+}
+
+/// A file that has been uploaded
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct UploadedAsset {
+    id: String,
+    /// the sha-1 of the file when it was uploaded, so a different file written to the same place is sent again
+    checksum: String,
+}
+
+/// What has been uploaded so far
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct UploadState {
+    /// every uploaded file, by the key of its upload
+    assets: BTreeMap<PathBuf, UploadedAsset>,
+    /// the server id of every album made, by its name
+    albums: BTreeMap<String, String>,
+}
+
+impl UploadState {
+    /// read the state of an earlier upload, or start afresh if there is none
+    pub fn load(path: &Path) -> Result<UploadState, Box<dyn std::error::Error>> {
+        match std::fs::read_to_string(path) {
+            Ok(contents) => Ok(serde_json::from_str(&contents)?),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(UploadState::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// write the state out, through a temporary file so it is never left half written
+    fn save(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let temp_path = path.with_extension("json.tmp");
+        std::fs::write(&temp_path, serde_json::to_string_pretty(self)?)?;
+        std::fs::rename(temp_path, path)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct AssetResponse {
+    id: String,
+    /// `created`, or `duplicate` if the server already had the file
+    status: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct AlbumResponse {
+    id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchResponse {
+    assets: SearchPage,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SearchPage {
+    items: Vec<SearchAsset>,
+    /// the number of the next page as a string, missing on the last page
+    next_page: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchAsset {
+    id: String,
+    /// base64 sha-1
+    checksum: String,
+}
+
+/// A sha-1 checksum as lowercase hex, from either hex or the base64 the Immich API uses
+fn hex_checksum(checksum: &str) -> Option<String> {
+    let checksum = checksum.trim();
+    if checksum.len() == 40 && checksum.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Some(checksum.to_lowercase());
+    }
+    let bytes = BASE64_STANDARD.decode(checksum).ok()?;
+    (bytes.len() == 20).then(|| bytes.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+/// Read checksums exported from Immich, one per line in hex or base64. Blank lines and lines starting with `#` are
+/// skipped.
+pub fn read_checksums(path: &Path) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let mut checksums = Vec::new();
+    for (number, line) in std::fs::read_to_string(path)?.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let checksum = hex_checksum(line).ok_or_else(|| {
+            format!(
+                "{}:{} is not a sha-1 checksum: `{}`",
+                path.display(),
+                number + 1,
+                line
+            )
+        })?;
+        checksums.push(checksum);
+    }
+    Ok(checksums)
+}
+
+/// the id Immich uses to tell files from the same device apart, made the same way as by the Immich CLI
+fn device_asset_id(file_name: &str, size: u64) -> String {
+    format!("{}-{}", file_name, size)
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect()
+}
+
+/// the SHA-1 of a file, as Immich stores it
+fn checksum(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha1::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// A client for the parts of the Immich API needed to upload files and make albums
+#[derive(Debug, Clone)]
+pub struct Client {
+    /// the address of the server, without the `/api`
+    server: String,
+    api_key: String,
+    http: reqwest::Client,
+}
+
+impl Client {
+    pub fn new(server: &str, api_key: &str) -> Client {
+        let server = server.trim_end_matches('/');
+        Client {
+            server: server.strip_suffix("/api").unwrap_or(server).to_owned(),
+            api_key: api_key.to_owned(),
+            http: reqwest::Client::new(),
+        }
+    }
+
+    fn request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        self.http
+            .request(method, format!("{}/api{}", self.server, path))
+            .header("x-api-key", &self.api_key)
+    }
+
+    async fn upload(
+        &self,
+        upload: &Upload,
+        checksum: &str,
+    ) -> Result<AssetResponse, Box<dyn std::error::Error>> {
+        let metadata = tokio::fs::metadata(&upload.path).await?;
+        let modified_at: DateTime<Utc> = metadata.modified()?.into();
+        let file_name = upload
+            .path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned();
+
+        let file = tokio::fs::File::open(&upload.path).await?;
+        let form = Form::new()
+            .text("deviceAssetId", device_asset_id(&file_name, metadata.len()))
+            .text("deviceId", DEVICE_ID)
+            .text(
+                "fileCreatedAt",
+                upload.taken_at.unwrap_or(modified_at).to_rfc3339(),
+            )
+            .text("fileModifiedAt", modified_at.to_rfc3339())
+            .part(
+                "assetData",
+                Part::stream_with_length(file, metadata.len()).file_name(file_name),
+            );
+
+        let response = self
+            .request(reqwest::Method::POST, "/assets")
+            .header("x-immich-checksum", checksum)
+            .multipart(form)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(response.json().await?)
+    }
+
+    /// the hex sha-1 and id of every asset on the server, including any in the trash, which it would also refuse
+    /// to take again
+    pub async fn assets(&self) -> Result<Vec<(String, String)>, Box<dyn std::error::Error>> {
+        let mut assets = Vec::new();
+        let mut page = 1;
+        loop {
+            let response: SearchResponse = self
+                .request(reqwest::Method::POST, "/search/metadata")
+                .json(&serde_json::json!({
+                    "page": page,
+                    "size": SEARCH_PAGE_SIZE,
+                    "withDeleted": true,
+                }))
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+
+            for asset in response.assets.items {
+                match hex_checksum(&asset.checksum) {
+                    Some(checksum) => assets.push((checksum, asset.id)),
+                    None => println!("Immich asset {} has no usable checksum", asset.id),
+                }
+            }
+            match response.assets.next_page.and_then(|next| next.parse().ok()) {
+                Some(next) => page = next,
+                None => return Ok(assets),
+            }
+        }
+    }
+
+    async fn create_album(
+        &self,
+        title: &str,
+        description: &str,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        let response = self
+            .request(reqwest::Method::POST, "/albums")
+            .json(&serde_json::json!({ "albumName": title, "description": description }))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(response.json::<AlbumResponse>().await?.id)
+    }
+
+    /// add assets to an album, the server ignores any that are already in it
+    async fn add_to_album(
+        &self,
+        album_id: &str,
+        asset_ids: &[&str],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.request(
+            reqwest::Method::PUT,
+            &format!("/albums/{}/assets", album_id),
+        )
+        .json(&serde_json::json!({ "ids": asset_ids }))
+        .send()
+        .await?
+        .error_for_status()?;
+        Ok(())
+    }
+
+    /// apply the same changes, e.g. `{"isFavorite": true}`, to many assets
+    async fn update_assets(
+        &self,
+        asset_ids: &[&str],
+        changes: serde_json::Value,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut body = changes;
+        body["ids"] = serde_json::json!(asset_ids);
+        self.request(reqwest::Method::PUT, "/assets")
+            .json(&body)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+/// Upload every file that isn't already in the state file, mark favourite and archived files, then make each
+/// album and add its files to it in order. Files that can't be read or fail to upload are reported and left
+/// out, so running this again retries just those.
+pub async fn upload(
+    client: &Client,
+    state_path: &Path,
+    uploads: &[Upload],
+    albums: &[UploadAlbum],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut state = UploadState::load(state_path)?;
+
+    // every file is hashed, as a later takeout written to the same output can put a different file at a path
+    // that was uploaded before
+    let checksums: Vec<io::Result<String>> = uploads
+        .par_iter()
+        .map(|upload| checksum(&upload.path))
+        .collect();
+    let (mut created, mut duplicates, mut failed) = (0, 0, 0);
+    let mut pending: Vec<(&Upload, String)> = Vec::new();
+    for (upload, checksum) in uploads.iter().zip(checksums) {
+        match checksum {
+            Ok(checksum) => {
+                if state
+                    .assets
+                    .get(&upload.key)
+                    .is_none_or(|asset| asset.checksum != checksum)
+                {
+                    pending.push((upload, checksum));
+                }
+            }
+            Err(e) => {
+                failed += 1;
+                println!("Hashing {}... FAILURE! `{}`", upload.path.display(), e);
+            }
+        }
+    }
+    let total = pending.len() + failed;
+    println!(
+        "Uploading {} files to immich, {} were uploaded before",
+        total,
+        uploads.len() - total
+    );
+
+    for chunk in pending.chunks(CONCURRENT_UPLOADS) {
+        let responses = futures::future::join_all(
+            chunk
+                .iter()
+                .map(|(upload, checksum)| client.upload(upload, checksum)),
+        )
+        .await;
+        for ((upload, checksum), response) in chunk.iter().zip(responses) {
+            match response {
+                Ok(response) => {
+                    match response.status.as_str() {
+                        "duplicate" => duplicates += 1,
+                        _ => created += 1,
+                    }
+                    let asset = UploadedAsset {
+                        id: response.id,
+                        checksum: checksum.to_string(),
+                    };
+                    state.assets.insert(upload.key.clone(), asset);
+                }
+                Err(e) => {
+                    failed += 1;
+                    println!("Uploading {}... FAILURE! `{}`", upload.path.display(), e);
+                }
+            }
+        }
+        state.save(state_path)?;
+        println!(
+            "[{}/{}] Uploaded to immich",
+            created + duplicates + failed,
+            total
+        );
+    }
+    println!(
+        "Uploaded {} files, {} were already on the server and {} failed",
+        created, duplicates, failed
+    );
+
+    let ids_of = |wanted: fn(&Upload) -> bool| -> Vec<&str> {
+        uploads
+            .iter()
+            .filter(|upload| wanted(upload))
+            .filter_map(|upload| state.assets.get(&upload.key))
+            .map(|asset| asset.id.as_str())
+            .collect()
+    };
+    for (ids, changes) in [
+        (
+            ids_of(|upload| upload.favorite),
+            serde_json::json!({ "isFavorite": true }),
+        ),
+        (
+            ids_of(|upload| upload.archived),
+            serde_json::json!({ "visibility": "archive" }),
+        ),
+    ] {
+        for batch in ids.chunks(BATCH_SIZE) {
+            client.update_assets(batch, changes.clone()).await?;
+        }
+    }
+
+    for album in albums {
+        let ids: Vec<&str> = album
+            .files
+            .iter()
+            .filter_map(|key| state.assets.get(key))
+            .map(|asset| asset.id.as_str())
+            .chain(album.existing.iter().map(String::as_str))
+            .collect();
+        if ids.is_empty() {
+            continue;
+        }
+
+        // saved as soon as it is made, so a run cut short while adding files doesn't make the album twice
+        let album_id = match state.albums.get(&album.name) {
+            Some(album_id) => album_id.clone(),
+            None => {
+                let album_id = client
+                    .create_album(&album.title, &album.description)
+                    .await?;
+                state.albums.insert(album.name.clone(), album_id.clone());
+                state.save(state_path)?;
+                album_id
+            }
+        };
+        for batch in ids.chunks(BATCH_SIZE) {
+            client.add_to_album(&album_id, batch).await?;
+        }
+        println!(
+            "Added {} files to {} album `{}`",
+            ids.len(),
+            if album.shared { "shared" } else { "owned" },
+            album.title
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        path::PathBuf,
+        sync::{Arc, Mutex},
+    };
+
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+        net::{TcpListener, TcpStream},
+    };
+
+    use super::{
+        checksum, device_asset_id, hex_checksum, read_checksums, upload, Client, Upload,
+        UploadAlbum, UploadState,
+    };
+
+    #[derive(Debug, Clone)]
+    struct Request {
+        method: String,
+        path: String,
+        headers: Vec<(String, String)>,
+        body: Vec<u8>,
+    }
+
+    impl Request {
+        fn header(&self, name: &str) -> Option<&str> {
+            self.headers
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.as_str())
+        }
+
+        fn json(&self) -> serde_json::Value {
+            serde_json::from_slice(&self.body).unwrap()
+        }
+    }
+
+    /// answer a request the way immich would, numbering new assets and albums in the order they arrive
+    fn respond(request: &Request, earlier: &[Request]) -> (u16, String) {
+        match (request.method.as_str(), request.path.as_str()) {
+            ("POST", "/api/assets") => {
+                let checksum = request.header("x-immich-checksum").unwrap();
+                let id = format!("asset-{}", &checksum[..8]);
+                let seen = earlier.iter().any(|earlier| {
+                    earlier.path == "/api/assets"
+                        && earlier.header("x-immich-checksum") == Some(checksum)
+                });
+                match seen {
+                    true => (200, format!(r#"{{"id": "{}", "status": "duplicate"}}"#, id)),
+                    false => (201, format!(r#"{{"id": "{}", "status": "created"}}"#, id)),
+                }
+            }
+            ("POST", "/api/albums") => {
+                let made = earlier.iter().filter(|r| r.path == "/api/albums").count();
+                (201, format!(r#"{{"id": "album-{}"}}"#, made + 1))
+            }
+            // adding to an album called `Flaky` fails the first time
+            ("PUT", path) if path.starts_with("/api/albums/") => {
+                let album = path.trim_start_matches("/api/albums/album-");
+                let number: usize = album.split('/').next().unwrap().parse().unwrap();
+                let flaky = earlier
+                    .iter()
+                    .filter(|r| r.path == "/api/albums")
+                    .nth(number - 1)
+                    .is_some_and(|made| made.json()["albumName"] == "Flaky");
+                match flaky && !earlier.iter().any(|earlier| earlier.path == path) {
+                    true => (500, String::new()),
+                    false => (200, "[]".to_owned()),
+                }
+            }
+            ("PUT", "/api/assets") => (204, String::new()),
+            // two pages of what the server already has, the second with an asset that has no checksum
+            ("POST", "/api/search/metadata") => match request.json()["page"].as_u64() {
+                Some(1) => (
+                    200,
+                    r#"{"assets": {"items": [{"id": "old-1", "checksum": "WnUyhkmk3y62Tw4Q9pEvYSCU6PQ="}], "nextPage": "2"}}"#
+                        .to_owned(),
+                ),
+                _ => (
+                    200,
+                    r#"{"assets": {"items": [{"id": "old-2", "checksum": "d3JvbmcgbGVuZ3Ro"}], "nextPage": null}}"#
+                        .to_owned(),
+                ),
+            },
+            _ => (404, String::new()),
+        }
+    }
+
+    async fn serve(stream: TcpStream, requests: Arc<Mutex<Vec<Request>>>) {
+        let mut reader = BufReader::new(stream);
+        // connections are kept alive, so keep answering until the client hangs up
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line).await.unwrap() == 0 {
+                return;
+            }
+            let mut parts = line.split_whitespace();
+            let method = parts.next().unwrap().to_owned();
+            let path = parts.next().unwrap().to_owned();
+
+            let mut headers = Vec::new();
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).await.unwrap();
+                let Some((key, value)) = line.trim_end().split_once(':') else {
+                    break;
+                };
+                headers.push((key.to_lowercase(), value.trim().to_owned()));
+            }
+
+            let length = headers
+                .iter()
+                .find(|(key, _)| key == "content-length")
+                .map_or(0, |(_, value)| value.parse().unwrap());
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).await.unwrap();
+
+            let request = Request {
+                method,
+                path,
+                headers,
+                body,
+            };
+            let (status, body) = {
+                let mut requests = requests.lock().unwrap();
+                let response = respond(&request, &requests);
+                requests.push(request);
+                response
+            };
+            let response = format!(
+                "HTTP/1.1 {} OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            );
+            reader
+                .get_mut()
+                .write_all(response.as_bytes())
+                .await
+                .unwrap();
+        }
+    }
+
+    /// start a stand in for an immich server, which records every request made to it
+    async fn mock_server() -> (String, Arc<Mutex<Vec<Request>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let recorded = requests.clone();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                tokio::spawn(serve(stream, recorded.clone()));
+            }
+        });
+        (url, requests)
+    }
+
+    #[tokio::test]
+    async fn test_upload_to_mock_server() {
+        let output = std::env::temp_dir().join(format!("gdog-immich-{}", std::process::id()));
+        std::fs::create_dir_all(output.join("albums/Trip")).unwrap();
+        let mut uploads = Vec::new();
+        for (name, contents, favorite, archived) in [
+            ("albums/Trip/Colosseum 1.jpg", "first", true, false),
+            ("albums/Trip/Louvre.jpg", "second", false, true),
+            ("general/Colosseum copy.jpg", "first", false, false),
+        ] {
+            std::fs::create_dir_all(output.join(name).parent().unwrap()).unwrap();
+            std::fs::write(output.join(name), contents).unwrap();
+            uploads.push(Upload {
+                path: output.join(name),
+                key: PathBuf::from(name),
+                taken_at: None,
+                favorite,
+                archived,
+            });
+        }
+        let albums = [UploadAlbum {
+            name: "Trip".to_owned(),
+            title: "Trip: Rome/Paris".to_owned(),
+            description: "Summer".to_owned(),
+            shared: false,
+            files: vec![
+                PathBuf::from("albums/Trip/Louvre.jpg"),
+                PathBuf::from("albums/Trip/Colosseum 1.jpg"),
+            ],
+            existing: vec!["asset-already-there".to_owned()],
+        }];
+
+        let (url, requests) = mock_server().await;
+        let client = Client::new(&format!("{}/api/", url), "secret");
+        let state_path = output.join("immich-upload.json");
+        upload(&client, &state_path, &uploads, &albums)
+            .await
+            .unwrap();
+
+        let first_id = format!("asset-{}", &checksum(&uploads[0].path).unwrap()[..8]);
+        let second_id = format!("asset-{}", &checksum(&uploads[1].path).unwrap()[..8]);
+        {
+            let requests = requests.lock().unwrap();
+            assert!(requests
+                .iter()
+                .all(|r| r.header("x-api-key") == Some("secret")));
+
+            let assets: Vec<&Request> = requests
+                .iter()
+                .filter(|r| r.method == "POST" && r.path == "/api/assets")
+                .collect();
+            assert_eq!(assets.len(), 3);
+            let sent = |request: &Request, text: &str| {
+                String::from_utf8_lossy(&request.body).contains(text)
+            };
+            let first = assets.iter().find(|r| sent(r, "Colosseum1.jpg-5")).unwrap();
+            assert_eq!(
+                first.header("x-immich-checksum"),
+                Some(checksum(&uploads[0].path).unwrap().as_str())
+            );
+            assert!(sent(first, "name=\"deviceId\"\r\n\r\ngdog"));
+            assert!(sent(first, "filename=\"Colosseum 1.jpg\""));
+
+            let album = requests.iter().find(|r| r.path == "/api/albums").unwrap();
+            assert_eq!(
+                album.json(),
+                serde_json::json!({"albumName": "Trip: Rome/Paris", "description": "Summer"})
+            );
+            let added = requests
+                .iter()
+                .find(|r| r.path == "/api/albums/album-1/assets")
+                .unwrap();
+            assert_eq!(
+                added.json()["ids"],
+                serde_json::json!([second_id, first_id, "asset-already-there"])
+            );
+
+            let updates: Vec<serde_json::Value> = requests
+                .iter()
+                .filter(|r| r.method == "PUT" && r.path == "/api/assets")
+                .map(Request::json)
+                .collect();
+            assert_eq!(
+                updates,
+                [
+                    serde_json::json!({"ids": [first_id], "isFavorite": true}),
+                    serde_json::json!({"ids": [second_id], "visibility": "archive"}),
+                ]
+            );
+        }
+
+        // a second run has nothing left to upload, and reuses the album it made
+        requests.lock().unwrap().clear();
+        upload(&client, &state_path, &uploads, &albums)
+            .await
+            .unwrap();
+        {
+            let requests = requests.lock().unwrap();
+            assert!(!requests.iter().any(|r| r.method == "POST"));
+            assert!(requests
+                .iter()
+                .any(|r| r.path == "/api/albums/album-1/assets"));
+        }
+
+        // a different file in the same place, from a newer takeout, is sent again
+        requests.lock().unwrap().clear();
+        std::fs::write(&uploads[1].path, "second, edited").unwrap();
+        upload(&client, &state_path, &uploads, &albums)
+            .await
+            .unwrap();
+        {
+            let requests = requests.lock().unwrap();
+            let assets: Vec<&Request> = requests
+                .iter()
+                .filter(|r| r.method == "POST" && r.path == "/api/assets")
+                .collect();
+            assert_eq!(assets.len(), 1);
+            assert_eq!(
+                assets[0].header("x-immich-checksum"),
+                Some(checksum(&uploads[1].path).unwrap().as_str())
+            );
+        }
+
+        std::fs::remove_dir_all(output).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_interrupted_album_is_not_made_twice() {
+        let output = std::env::temp_dir().join(format!("gdog-immich-flaky-{}", std::process::id()));
+        std::fs::create_dir_all(&output).unwrap();
+        std::fs::write(output.join("beach.jpg"), "sand").unwrap();
+        let uploads = [Upload {
+            path: output.join("beach.jpg"),
+            key: PathBuf::from("albums/Flaky/beach.jpg"),
+            taken_at: None,
+            favorite: false,
+            archived: false,
+        }];
+        let albums = [UploadAlbum {
+            name: "Flaky".to_owned(),
+            title: "Flaky".to_owned(),
+            description: String::new(),
+            shared: false,
+            files: vec![PathBuf::from("albums/Flaky/beach.jpg")],
+            existing: Vec::new(),
+        }];
+
+        let (url, requests) = mock_server().await;
+        let client = Client::new(&url, "secret");
+        let state_path = output.join("immich-upload.json");
+        assert!(upload(&client, &state_path, &uploads, &albums)
+            .await
+            .is_err());
+        upload(&client, &state_path, &uploads, &albums)
+            .await
+            .unwrap();
+
+        let requests = requests.lock().unwrap();
+        let made = requests.iter().filter(|r| r.path == "/api/albums").count();
+        assert_eq!(made, 1);
+        let added = requests
+            .iter()
+            .filter(|r| r.path == "/api/albums/album-1/assets")
+            .count();
+        assert_eq!(added, 2);
+
+        std::fs::remove_dir_all(output).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_unreadable_file_is_left_out() {
+        let output =
+            std::env::temp_dir().join(format!("gdog-immich-unreadable-{}", std::process::id()));
+        std::fs::create_dir_all(&output).unwrap();
+        std::fs::write(output.join("beach.jpg"), "sand").unwrap();
+        let uploads = ["gone.jpg", "beach.jpg"].map(|name| Upload {
+            path: output.join(name),
+            key: PathBuf::from(name),
+            taken_at: None,
+            favorite: false,
+            archived: false,
+        });
+
+        let (url, requests) = mock_server().await;
+        let client = Client::new(&url, "secret");
+        let state_path = output.join("immich-upload.json");
+        upload(&client, &state_path, &uploads, &[]).await.unwrap();
+
+        let posted = requests
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|r| r.method == "POST" && r.path == "/api/assets")
+            .count();
+        assert_eq!(posted, 1);
+        let state = UploadState::load(&state_path).unwrap();
+        assert_eq!(
+            state.assets.keys().collect::<Vec<_>>(),
+            [&PathBuf::from("beach.jpg")]
+        );
+
+        std::fs::remove_dir_all(output).unwrap();
+    }
+
+    #[test]
+    fn test_device_asset_id() {
+        assert_eq!(
+            device_asset_id("IMG 1234 (1).jpg", 2048),
+            "IMG1234(1).jpg-2048"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_existing_assets_are_fetched_page_by_page() {
+        let (url, requests) = mock_server().await;
+        let assets = Client::new(&url, "secret").assets().await.unwrap();
+        assert_eq!(
+            assets,
+            [(
+                "5a75328649a4df2eb64f0e10f6912f612094e8f4".to_owned(),
+                "old-1".to_owned()
+            )]
+        );
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].json()["withDeleted"], true);
+    }
+
+    #[test]
+    fn test_checksums_in_hex_or_base64() {
+        let hex = "5a75328649a4df2eb64f0e10f6912f612094e8f4";
+        assert_eq!(
+            hex_checksum("WnUyhkmk3y62Tw4Q9pEvYSCU6PQ=").as_deref(),
+            Some(hex)
+        );
+        assert_eq!(hex_checksum(&hex.to_uppercase()).as_deref(), Some(hex));
+        assert_eq!(hex_checksum("not a checksum"), None);
+
+        let path = std::env::temp_dir().join(format!("gdog-checksums-{}.txt", std::process::id()));
+        std::fs::write(
+            &path,
+            format!(
+                "# exported from immich\n{}\n\nWnUyhkmk3y62Tw4Q9pEvYSCU6PQ=\n",
+                hex
+            ),
+        )
+        .unwrap();
+        assert_eq!(read_checksums(&path).unwrap(), [hex, hex]);
+        std::fs::write(&path, "5a75\n").unwrap();
+        assert!(read_checksums(&path)
+            .unwrap_err()
+            .to_string()
+            .ends_with(":1 is not a sha-1 checksum: `5a75`"));
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,235 @@
+//! Estimates dates for media files that have none, from the dated files around them in the same folder.
+//!
+//! Camera file names count up as photos are taken, so an undated `IMG_1234` between a dated `IMG_1233` and
+//! `IMG_1235` was taken between them, and is placed in proportion to its number. Files without such a number are
+//! placed between the dated files either side of them in takeout order instead, which is less reliable. Either
+//! way, the closer together the neighbours were taken, the more precise and confident the estimate.
+
+use std::sync::LazyLock;
+
+use chrono::{DateTime, Duration, Local};
+use regex::Regex;
+
+use crate::{split_duplicate_counter, DatePrecision};
+
+/// neighbours further apart than this in a camera's numbering are probably from a different roll of the counter
+const MAX_SEQUENCE_GAP: u64 = 100;
+
+/// confidence out of 100 of an estimate from camera numbering or takeout order, when the neighbours are close
+const SEQUENCE_CONFIDENCE: u32 = 90;
+const ORDER_CONFIDENCE: u32 = 60;
+
+/// `IMG_1234`, `DSC00042`, `P1010001`
+static SEQUENCE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^([^0-9]*)([0-9]{3,})$").unwrap());
+
+/// A media file in a folder, along with the date it is known to have been taken if there is one
+#[derive(Debug, Clone)]
+pub struct Item<'a> {
+    pub file_stem: &'a str,
+    pub date: Option<(DateTime<Local>, DatePrecision)>,
+}
+
+/// An estimated date for an undated file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Estimate {
+    pub date: DateTime<Local>,
+    pub precision: DatePrecision,
+    /// out of 100
+    pub confidence: u8,
+}
+
+/// the camera prefix and number of a file stem, e.g. `IMG_1234(1)` -> (`IMG_`, 1234)
+fn sequence_number(file_stem: &str) -> Option<(&str, u64)> {
+    let file_stem = split_duplicate_counter(file_stem).map_or(file_stem, |(stem, _)| stem);
+    let captures = SEQUENCE.captures(file_stem)?;
+    Some((captures.get(1)?.as_str(), captures[2].parse().ok()?))
+}
+
+/// how precise a date placed between two others can be, or `None` if they are too far apart to say
+fn span_precision(before: &DateTime<Local>, after: &DateTime<Local>) -> Option<DatePrecision> {
+    let (first, last) = (before.min(after), before.max(after));
+    if *last - *first <= Duration::hours(1) {
+        Some(DatePrecision::Time)
+    } else if first.date_naive() == last.date_naive() {
+        Some(DatePrecision::Day)
+    } else if first.format("%Y-%m").to_string() == last.format("%Y-%m").to_string() {
+        Some(DatePrecision::Month)
+    } else if first.format("%Y").to_string() == last.format("%Y").to_string() {
+        Some(DatePrecision::Year)
+    } else {
+        None
+    }
+}
+
+/// place a date `position` of the way from `before` to `after`
+fn between(
+    before: (DateTime<Local>, DatePrecision),
+    after: (DateTime<Local>, DatePrecision),
+    position: f64,
+    confidence: u32,
+) -> Option<Estimate> {
+    let precision = span_precision(&before.0, &after.0)?
+        .min(before.1)
+        .min(after.1);
+    let offset = (after.0 - before.0).num_milliseconds() as f64 * position;
+    let weight = match precision {
+        DatePrecision::Time => 100,
+        DatePrecision::Day => 75,
+        DatePrecision::Month => 50,
+        DatePrecision::Year => 25,
+    };
+
+    Some(Estimate {
+        date: before.0 + Duration::milliseconds(offset as i64),
+        precision,
+        confidence: (confidence * weight / 100) as u8,
+    })
+}
+
+/// the estimate from the closest dated files either side in the same camera numbering
+fn from_sequence(items: &[Item], index: usize) -> Option<Estimate> {
+    let (prefix, number) = sequence_number(items[index].file_stem)?;
+
+    let mut before: Option<(u64, (DateTime<Local>, DatePrecision))> = None;
+    let mut after: Option<(u64, (DateTime<Local>, DatePrecision))> = None;
+    for item in items {
+        let (Some(date), Some((item_prefix, item_number))) =
+            (item.date, sequence_number(item.file_stem))
+        else {
+            continue;
+        };
+        if item_prefix != prefix {
+            continue;
+        }
+        if item_number < number && before.is_none_or(|(n, _)| item_number > n) {
+            before = Some((item_number, date));
+        }
+        if item_number > number && after.is_none_or(|(n, _)| item_number < n) {
+            after = Some((item_number, date));
+        }
+    }
+
+    let ((first, before), (last, after)) = (before?, after?);
+    // numbers count up as time goes on, if they don't the counter has been reset in between
+    if last - first > MAX_SEQUENCE_GAP || after.0 < before.0 {
+        return None;
+    }
+    let position = (number - first) as f64 / (last - first) as f64;
+    between(before, after, position, SEQUENCE_CONFIDENCE)
+}
+
+/// the estimate from the closest dated files either side in takeout order
+fn from_order(items: &[Item], index: usize) -> Option<Estimate> {
+    let first = (0..index).rev().find(|i| items[*i].date.is_some())?;
+    let last = (index + 1..items.len()).find(|i| items[*i].date.is_some())?;
+    let position = (index - first) as f64 / (last - first) as f64;
+    between(
+        items[first].date?,
+        items[last].date?,
+        position,
+        ORDER_CONFIDENCE,
+    )
+}
+
+/// Estimate a date for every undated item of a folder, given in takeout order. Only the dates the items came with
+/// are used, so estimates never build on each other.
+pub fn estimate(items: &[Item]) -> Vec<Option<Estimate>> {
+    (0..items.len())
+        .map(|index| match items[index].date {
+            Some(_) => None,
+            None => from_sequence(items, index).or_else(|| from_order(items, index)),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+
+    use super::{estimate, Item};
+    use crate::DatePrecision;
+
+    fn local(text: &str) -> DateTime<Local> {
+        let naive = NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S").unwrap();
+        Local.from_local_datetime(&naive).unwrap()
+    }
+
+    fn item<'a>(file_stem: &'a str, date: Option<&str>) -> Item<'a> {
+        Item {
+            file_stem,
+            date: date.map(|date| (local(date), DatePrecision::Time)),
+        }
+    }
+
+    #[test]
+    fn test_camera_numbering() {
+        let items = [
+            item("IMG_1230", Some("2019-01-01 12:00:00")),
+            item("IMG_1234", None),
+            item("IMG_1234(1)", None),
+            // takeout order puts this next to the undated files, but its numbering doesn't
+            item("Screenshot", Some("2019-03-01 08:00:00")),
+            item("IMG_1240", Some("2019-01-01 12:10:00")),
+        ];
+        let estimates = estimate(&items);
+
+        let found = estimates[1].clone().unwrap();
+        assert_eq!(found.date, local("2019-01-01 12:04:00"));
+        assert_eq!(found.precision, DatePrecision::Time);
+        assert_eq!(found.confidence, 90);
+        assert_eq!(estimates[2], estimates[1]);
+        assert!(estimates[0].is_none() && estimates[3].is_none() && estimates[4].is_none());
+    }
+
+    #[test]
+    fn test_takeout_order() {
+        let items = [
+            item("Beach", Some("2019-07-04 09:00:00")),
+            item("Sunset", None),
+            item("Dinner", None),
+            item("Fireworks", Some("2019-07-04 21:00:00")),
+            item("Hangover", None),
+        ];
+        let estimates = estimate(&items);
+
+        let found = estimates[1].clone().unwrap();
+        assert_eq!(found.date, local("2019-07-04 13:00:00"));
+        assert_eq!(found.precision, DatePrecision::Day);
+        assert_eq!(found.confidence, 45);
+        assert_eq!(
+            estimates[2].clone().unwrap().date,
+            local("2019-07-04 17:00:00")
+        );
+        // nothing dated after it to place it before
+        assert!(estimates[4].is_none());
+    }
+
+    #[test]
+    fn test_neighbours_too_far_apart() {
+        let items = [
+            item("IMG_0001", Some("2015-01-01 12:00:00")),
+            item("IMG_0002", None),
+            item("IMG_0003", Some("2018-01-01 12:00:00")),
+        ];
+        assert!(estimate(&items).iter().all(Option::is_none));
+
+        // the numbering can't be trusted across a big gap or once the counter has been reset, so these are only
+        // placed by takeout order
+        for items in [
+            [
+                item("IMG_0100", Some("2019-06-01 12:00:00")),
+                item("IMG_0200", None),
+                item("IMG_0300", Some("2019-06-01 12:30:00")),
+            ],
+            [
+                item("IMG_0500", Some("2019-06-01 12:30:00")),
+                item("IMG_0501", None),
+                item("IMG_0502", Some("2019-06-01 12:00:00")),
+            ],
+        ] {
+            let found = estimate(&items)[1].clone().unwrap();
+            assert_eq!(found.date, local("2019-06-01 12:15:00"));
+            assert_eq!(found.confidence, 60);
+        }
+    }
+}
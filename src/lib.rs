@@ -1,42 +1,292 @@
+mod album;
+mod config;
+mod embedded_date;
+mod file_type;
+mod filename_date;
+mod folder_date;
+mod immich;
+mod interpolate;
+mod locale;
+mod review;
+mod sidecar;
+mod source;
+mod truncation;
+
+pub use config::Config;
+pub use sidecar::SidecarIndex;
+
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque},
+    io::{self, BufRead, Write},
     path::{Path, PathBuf},
-    process::{Command, Stdio},
+    process::Stdio,
     sync::{
         atomic::{AtomicUsize, Ordering},
-        Arc, RwLock,
+        Arc,
     },
 };
 
-use chrono::{DateTime, Local, LocalResult, NaiveDateTime, NaiveTime, TimeZone};
-use fuzzywuzzy::{fuzz, process::extract_one, utils};
-use rayon::prelude::{IntoParallelRefMutIterator, ParallelIterator};
+use chrono::{DateTime, Local, LocalResult, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use fuzzywuzzy::{fuzz, process::extract_without_order, utils};
+use rayon::prelude::{IntoParallelRefIterator, IntoParallelRefMutIterator, ParallelIterator};
 use serde::{Deserialize, Serialize};
+use sha1::Sha1;
 use sha3::{Digest, Sha3_256};
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::io::AsyncReadExt;
+
+use crate::{
+    album::Album,
+    file_type::FileType,
+    filename_date::{DatePattern, FilenameDate},
+    immich::{Upload, UploadAlbum},
+    interpolate::Item,
+    locale::Locale,
+    review::{Decision, Decisions},
+    sidecar::Sidecar,
+    source::{hash_reader, FileHashes, TakeoutFs},
+};
 
 const IGNORED_TYPES: &[&str] = &["html", "hash"];
-const IGNORED_FILES: &[&str] = &[
-    "metadata.json",
-    "shared_album_comments.json",
-    "user-generated-memory-titles.json",
-    "print-subscriptions.json",
+/// extensions of videos, which are only used as the cover of an album that has no photos
+const VIDEO_TYPES: &[&str] = &[
+    "mp4", "mov", "m4v", "3gp", "avi", "mts", "mpeg", "mpg", "asf", "wmv", "mkv", "webm",
 ];
 
-#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+/// fuzzy matches scoring at least this are used as the sidecar of a media file
+const FUZZY_MATCH_SCORE: u8 = 90;
+/// lower scoring fuzzy matches are kept as alternatives to offer during review
+const FUZZY_ALTERNATIVE_SCORE: u8 = 60;
+const FUZZY_MAX_CANDIDATES: usize = 5;
+/// an album is often made some time after its photos were taken, so its date is only trusted to the month
+const ALBUM_DATE_PRECISION: DatePrecision = DatePrecision::Month;
+/// files which are never media or sidecars, in any language
+fn is_ignored_file(file_name: &str) -> bool {
+    locale::is_ignored_file(file_name) || locale::is_album_metadata(file_name)
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 enum DestLocation {
-    Shared,
+    /// an album someone shared with the user, by its output folder name
+    Shared {
+        album: String,
+    },
     General,
-    Albums,
+    /// one of the user's own albums, by its output folder name
+    Albums {
+        album: String,
+    },
+}
+
+impl DestLocation {
+    /// the album a file sent here is in, if any
+    fn membership(&self) -> Option<AlbumMembership> {
+        match self {
+            DestLocation::Shared { album } => Some(AlbumMembership {
+                album: album.clone(),
+                shared: true,
+            }),
+            DestLocation::Albums { album } => Some(AlbumMembership {
+                album: album.clone(),
+                shared: false,
+            }),
+            DestLocation::General => None,
+        }
+    }
+
+    /// which of a set of duplicate files to keep, lowest first: albums, then shared albums, then general photos
+    fn keep_rank(&self) -> u8 {
+        match self {
+            DestLocation::Albums { .. } => 0,
+            DestLocation::Shared { .. } => 1,
+            DestLocation::General => 2,
+        }
+    }
+}
+
+/// An album a media file is in, by its output folder name
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct AlbumMembership {
+    album: String,
+    /// whether the album was shared with the user by someone else
+    shared: bool,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+impl AlbumMembership {
+    /// where the album is written, relative to the output directory
+    fn folder(&self) -> PathBuf {
+        let root = if self.shared { "shared" } else { "albums" };
+        Path::new(root).join(&self.album)
+    }
+}
+
+/// A media file that was left out of the output
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SkippedFile {
+    media_path: PathBuf,
+    reason: SkipReason,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum SkipReason {
+    /// the same file is in the takeout more than once, and this copy of it was dropped
+    Duplicate { kept: PathBuf },
+    /// immich already has the file, as the asset with this id if it is known
+    InImmich {
+        asset_id: Option<String>,
+        /// the album the file was going to be copied to, so it can still be added to it on the server
+        album: Option<AlbumMembership>,
+    },
+    /// the file couldn't be read, e.g. a corrupt or cut short archive entry
+    Unreadable { error: String },
+}
+
+/// How a photo that is in several albums is written out
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum AlbumOutput {
+    /// a full copy in the folder of every album
+    #[default]
+    Copy,
+    /// one copy, hardlinked into the folders of the other albums
+    Hardlink,
+    /// one copy, with every album it is in listed in `albums.json`
+    Manifest,
+}
+
+impl std::str::FromStr for AlbumOutput {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<AlbumOutput, String> {
+        match s.to_lowercase().as_str() {
+            "copy" => Ok(AlbumOutput::Copy),
+            "hardlink" => Ok(AlbumOutput::Hardlink),
+            "manifest" => Ok(AlbumOutput::Manifest),
+            _ => Err(format!(
+                "Unknown album output `{}`, expected copy, hardlink or manifest",
+                s
+            )),
+        }
+    }
+}
+
+/// An album in `albums.json`, with everything needed to recreate it on a server like Immich. Paths are relative to
+/// the output directory.
+#[derive(Debug, Serialize)]
+struct ManifestAlbum<'a> {
+    title: &'a str,
+    #[serde(skip_serializing_if = "str::is_empty")]
+    description: &'a str,
+    /// when the album was made, if google knows
+    date: Option<DateTime<Utc>>,
+    /// the place the album was given in google photos, which immich albums have nowhere to keep
+    #[serde(skip_serializing_if = "Option::is_none")]
+    location: Option<&'a str>,
+    /// whether the album was shared with the user by someone else
+    shared: bool,
+    /// the album's own folder
+    folder: PathBuf,
+    /// a file to show for the album, the first photo in it, or the first video if it has no photos
+    cover: Option<PathBuf>,
+    /// the files in the album, oldest first. A file in several albums is listed by the same path in each, however
+    /// many copies of it were written, so it only needs uploading once.
+    files: Vec<PathBuf>,
+    /// the ids of files in the album that immich already had, so weren't written out
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    in_immich: Vec<&'a str>,
+}
+
+/// How much of a date is actually known, e.g. a folder named "Photos from 2014" only gives the year
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum DatePrecision {
+    Year,
+    Month,
+    Day,
+    /// down to the time of day
+    Time,
+}
+
+impl std::str::FromStr for DatePrecision {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<DatePrecision, String> {
+        match s.to_lowercase().as_str() {
+            "year" => Ok(DatePrecision::Year),
+            "month" => Ok(DatePrecision::Month),
+            "day" => Ok(DatePrecision::Day),
+            "time" => Ok(DatePrecision::Time),
+            _ => Err(format!(
+                "Unknown date precision `{}`, expected year, month, day or time",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub enum MatchSource {
     NoMatch,
     JsonFile,
-    FileName,
-    DirectoryName,
-    FuzzyMatch { score: u8 },
+    /// the date was read from the metadata of the file itself, from the named tag e.g. `EXIF:DateTimeOriginal`
+    Embedded {
+        tag: String,
+    },
+    /// the date was read from the file name by the named pattern
+    FileName {
+        pattern: String,
+        precision: DatePrecision,
+    },
+    /// the date was read from the name of the folder holding the file, and is the start of the range it names
+    DirectoryName {
+        folder: String,
+        precision: DatePrecision,
+        /// the last day of the range, e.g. 31 December for "Photos from 2014"
+        end: NaiveDate,
+    },
+    FuzzyMatch {
+        score: u8,
+    },
+    /// the date the album holding the file was made, the last resort for files with no other date
+    AlbumDate,
+    /// the date was estimated from the dated files around it in its folder, see `interpolate`, with a confidence
+    /// out of 100
+    Interpolated {
+        confidence: u8,
+        precision: DatePrecision,
+    },
+    /// a sidecar in another directory with the same title, `timestamp` is set if a date in the file name agreed
+    /// with when the sidecar says the photo was taken, rather than going on the title alone
+    CrossDirectory {
+        timestamp: bool,
+    },
+}
+
+impl MatchSource {
+    /// how much of the date found this way can be trusted
+    fn precision(&self) -> DatePrecision {
+        match self {
+            MatchSource::FileName { precision, .. }
+            | MatchSource::DirectoryName { precision, .. }
+            | MatchSource::Interpolated { precision, .. } => *precision,
+            MatchSource::AlbumDate => ALBUM_DATE_PRECISION,
+            _ => DatePrecision::Time,
+        }
+    }
+}
+
+/// A sidecar which might belong to a media file, found by fuzzy matching file names
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FuzzyCandidate {
+    json_path: PathBuf,
+    score: u8,
+}
+
+/// A sidecar a file would have fuzzy matched, had a better scoring file not claimed it first
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FuzzyConflict {
+    json_path: PathBuf,
+    score: u8,
+    taken_by: PathBuf,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -47,383 +297,987 @@ pub struct MediaFile {
     json_path: Option<PathBuf>,
     media_creation_date: Option<DateTime<Local>>,
     match_source: MatchSource,
+    /// every fuzzy match found for the file, best first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    fuzzy_candidates: Vec<FuzzyCandidate>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    fuzzy_conflicts: Vec<FuzzyConflict>,
+    /// every album the file is in, found when duplicates are removed, as the same photo can be in many albums
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    albums: Vec<AlbumMembership>,
+    /// where the file is copied or linked to for each album it is in besides the one at `destination_path`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    album_copies: Vec<PathBuf>,
 }
 
+/// Upload and solving process for google takeout import:
+/// 1. Find all media/json pairs in the takeout directory and match them together
+/// 2. Use a fuzzy matching algorithm to find missing pairs - getting user confirmation for each if a review file is set
+/// 3. Iterate through pairs, rename/convert files that have the incorrect file format
+/// 4. Use exiftool to extract information from the .json file and apply it to the media file (subprocess)
+/// 5. Use directory and filenames to add exif information to files which do not have it
+/// 6. Move folders around to valid subfolders to prepare for upload
+/// 7. Remove duplicates (e.g. in order of preference, e.g. nuke shared folders before anything else)
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Processor<'a> {
     pub takeout_directory: &'a str,
     pub output_directory: &'a str,
+    inputs: Vec<PathBuf>,
+    /// language code of the export, detected while loading files unless set with `set_locale`
+    locale: Option<String>,
     media_files: Vec<MediaFile>,
     json_files: HashSet<PathBuf>,
+    #[serde(skip)]
+    takeout: TakeoutFs,
+    #[serde(skip)]
+    sidecars: SidecarIndex,
+    /// the metadata of every album, by the folder it is in
+    albums: BTreeMap<PathBuf, Album>,
+    /// previous answers to the fuzzy match review, only set when reviewing
+    #[serde(skip)]
+    decisions: Option<Decisions>,
+    /// patterns for dates in file names, the built in ones plus any from the config
+    #[serde(skip)]
+    date_patterns: Vec<DatePattern>,
+    /// dates found without a sidecar that are less precise than this are kept out of the EXIF date tags
+    exif_precision: DatePrecision,
+    /// estimate dates for files that have none from the files around them
+    interpolate: bool,
+    /// how files that are in several albums are written out
+    album_output: AlbumOutput,
+    /// the takeout folder of every album, by its output folder name
+    album_sources: BTreeMap<String, PathBuf>,
+    /// the sha-1 of every asset immich already has, with its id there if it is known
+    #[serde(skip)]
+    immich_assets: HashMap<String, Option<String>>,
+    /// every media file left out of the output, and why
+    skipped: Vec<SkippedFile>,
 }
 
-/// Upload and solving process for google takeout import:
-/// 1. Find all media/json pairs in the takeout directory and match them together
-/// 2. Use a fuzzy matching algorithm to find missing pairs - getting user confirmation for each and then renaming
-/// 3. Iterate through pairs, rename/convert files that have the incorrect file format
-/// 4. Use exiftool to extract information from the .json file and apply it to the media file (subprocess)
-/// 5. Use directory and filenames to add exif information to files which do not have it
-/// 6. Move folders around to valid subfolders to prepare for upload
-/// 7. Remove duplicates (e.g. in order of preference, e.g. nuke shared folders before anything else)
+/// split the duplicate counter off the end of a file stem, e.g. `IMG_1234(1)` -> (`IMG_1234`, 1)
+fn split_duplicate_counter(file_stem: &str) -> Option<(&str, u32)> {
+    let inner = file_stem.strip_suffix(')')?;
+    let open = inner.rfind('(')?;
+    let number = inner[open + 1..].parse::<u32>().ok()?;
+    Some((&file_stem[..open], number))
+}
 
-fn try_parse_8_char_date(input: &str) -> Option<DateTime<Local>> {
-    // if we see  8 digits in a row, that's probably a date in the format YYYYMMDD
-    let mut date_string = String::new();
-    for c in input.chars() {
-        if c.is_ascii_digit() {
-            date_string.push(c);
-            if date_string.len() == 8 {
-                break;
-            }
-        } else {
-            date_string.clear();
-        }
+/// the file a sidecar was matched to by anything other than fuzzy matching, which a fuzzy pick can't take from it
+fn held_by_match<'a>(media_files: &'a [MediaFile], json_path: &Path) -> Option<&'a Path> {
+    media_files
+        .iter()
+        .find(|file| {
+            !matches!(file.match_source, MatchSource::FuzzyMatch { .. })
+                && file.json_path.as_deref() == Some(json_path)
+        })
+        .map(|file| file.media_path.as_path())
+}
+
+/// `path`, or a numbered version of it if there is already a file there or another file is going to be copied there
+fn free_destination(path: &Path, planned: &HashSet<PathBuf>, counter: &mut usize) -> PathBuf {
+    if !path.exists() && !planned.contains(path) {
+        return path.to_path_buf();
     }
 
-    if date_string.len() == 8 {
-        let year = date_string[0..4].parse::<i32>().unwrap();
-        let month = date_string[4..6].parse::<u32>().unwrap();
-        let day = date_string[6..8].parse::<u32>().unwrap();
+    let mut new_path = path.to_path_buf();
+    new_path.set_file_name(format!(
+        "{}_{}.{}",
+        path.file_stem().unwrap().to_str().unwrap(),
+        counter,
+        path.extension().unwrap().to_str().unwrap()
+    ));
+    *counter += 1;
+    new_path
+}
 
-        // check that this is a valid date
-        if let Some(date) = chrono::NaiveDate::from_ymd_opt(year, month, day) {
-            let date_time = NaiveDateTime::new(date, NaiveTime::from_hms_opt(0, 0, 0).unwrap());
-            if let LocalResult::Single(local) = Local.from_local_datetime(&date_time) {
-                return Some(local);
-            }
-        }
+/// The exiftool arguments to write a date found without a sidecar. Every date goes into
+/// `XMP-photoshop:DateCreated`, which can hold just a year or month, but only dates at least as precise as
+/// `exif_precision` go into the EXIF, PNG and QuickTime tags, since those always claim a full time.
+fn inferred_date_args(
+    date: &DateTime<Local>,
+    precision: DatePrecision,
+    exif_precision: DatePrecision,
+) -> Vec<String> {
+    let partial = match precision {
+        DatePrecision::Year => date.format("%Y"),
+        DatePrecision::Month => date.format("%Y:%m"),
+        DatePrecision::Day => date.format("%Y:%m:%d"),
+        DatePrecision::Time => date.format("%Y:%m:%d %H:%M:%S%:z"),
+    };
+    let mut args = vec![format!("-XMP-photoshop:DateCreated={}", partial)];
+
+    if precision >= exif_precision {
+        let local = date.format("%Y:%m:%d %H:%M:%S");
+        let offset = date.format("%:z");
+        // QuickTime dates are in UTC
+        let utc = date.with_timezone(&Utc).format("%Y:%m:%d %H:%M:%S");
+        args.extend([
+            format!("-EXIF:DateTimeOriginal={}", local),
+            format!("-EXIF:CreateDate={}", local),
+            format!("-EXIF:OffsetTimeOriginal={}", offset),
+            format!("-XMP-exif:DateTimeOriginal={}{}", local, offset),
+            format!("-PNG:CreationTime={}", local),
+            format!("-QuickTime:CreateDate={}", utc),
+            format!("-QuickTime:TrackCreateDate={}", utc),
+            format!("-QuickTime:MediaCreateDate={}", utc),
+        ]);
     }
-    None
+
+    args.push("-overwrite_original".to_owned());
+    args
 }
 
-fn json_path_from_media_path(media_path: &Path) -> Vec<PathBuf> {
-    // 2. If the file has ` (x)` where x is a number appended to the end:
-    //  e.g. take the filename, strip the last (2+size_of_int_in_chars) from the filestem
-    //  readd the file ext
-    //  Append ` (x).json`
-    let mut options = vec![];
-    let mut json_filename = format!("{}.json", media_path.display());
-    options.push(json_filename);
-    let file_stem = media_path.file_stem().unwrap().to_str().unwrap();
-    if file_stem.ends_with(')') {
-        // extract the string between the two brackets (X)
-        let mut extracted_string = String::new();
-        for c in file_stem.chars().rev().skip(1) {
-            if c == '(' {
-                break;
-            }
-            extracted_string.push(c);
+/// The exiftool arguments to give a file the location of its album, unless it already has a location of its own
+fn album_location_args(latitude: f64, longitude: f64) -> Vec<String> {
+    vec![
+        "-if".to_owned(),
+        "not $GPSLatitude and not $GPSCoordinates".to_owned(),
+        format!("-GPSLatitude={}", latitude.abs()),
+        format!("-GPSLatitudeRef={}", if latitude < 0.0 { "S" } else { "N" }),
+        format!("-GPSLongitude={}", longitude.abs()),
+        format!(
+            "-GPSLongitudeRef={}",
+            if longitude < 0.0 { "W" } else { "E" }
+        ),
+        // videos
+        format!("-Keys:GPSCoordinates={}, {}", latitude, longitude),
+        "-overwrite_original".to_owned(),
+    ]
+}
+
+/// how an exiftool run went, for the progress output
+fn exiftool_outcome(process: std::io::Result<std::process::Output>) -> String {
+    match process {
+        Err(e) => format!("FAILURE! `{}`", e),
+        Ok(process) if process.status.success() => "Success!".to_owned(),
+        Ok(process) => format!(
+            "FAILURE! `{}` `{}`",
+            String::from_utf8_lossy(&process.stderr)
+                .replace('\r', "")
+                .replace('\n', "  "),
+            String::from_utf8_lossy(&process.stdout)
+                .replace('\r', "")
+                .replace('\n', "  ")
+        ),
+    }
+}
+
+impl<'a> Processor<'_> {
+    pub fn new(takeout_directory: &'a str, output_directory: &'a str) -> Processor<'a> {
+        Processor {
+            takeout_directory,
+            output_directory,
+            inputs: vec![PathBuf::from(takeout_directory)],
+            locale: None,
+            media_files: Vec::new(),
+            json_files: HashSet::new(),
+            takeout: TakeoutFs::default(),
+            sidecars: SidecarIndex::default(),
+            albums: BTreeMap::new(),
+            decisions: None,
+            date_patterns: filename_date::builtin_patterns(),
+            exif_precision: DatePrecision::Day,
+            interpolate: false,
+            album_output: AlbumOutput::default(),
+            album_sources: BTreeMap::new(),
+            immich_assets: HashMap::new(),
+            skipped: Vec::new(),
         }
+    }
 
-        //flip string
-        extracted_string = extracted_string.chars().rev().collect();
+    /// add another input to read from, either an extracted takeout directory or a takeout .zip archive
+    pub fn add_input(&mut self, input: impl Into<PathBuf>) {
+        self.inputs.push(input.into());
+    }
 
-        // if the extracted string is NOT a number, break
-        if let Ok(number) = extracted_string.parse::<u32>() {
-            let file_ext = media_path.extension().unwrap().to_str().unwrap();
-            let file_parent = media_path.parent().unwrap();
-            json_filename = file_stem[..file_stem.len() - extracted_string.len() - 2].to_string();
-            json_filename = file_parent
-                .join(format!("{}.{}({}).json", json_filename, file_ext, number))
-                .to_str()
-                .unwrap()
-                .to_string();
-            options.insert(0, json_filename);
+    /// use the folder names of the given language (e.g. "de") instead of detecting them from the export
+    pub fn set_locale(&mut self, code: &str) -> Result<(), Box<dyn std::error::Error>> {
+        if locale::by_code(code).is_none() {
+            return Err(format!("Unknown takeout locale: {}", code).into());
         }
+        self.locale = Some(code.to_owned());
+        Ok(())
     }
 
-    for option in options.clone() {
-        // 3. If the file is of type heic, the json file has NO extension, otherwise it's as usual:
-        //  - heic: filename.heic -> filename.json
-        //  - non-heic: filename.jpg -> filename.jpg.json
-        if media_path.extension().unwrap().to_ascii_lowercase() == "heic" {
-            options.push(option.replace(".heic.json", ".json"));
-            options.push(option.replace(".HEIC.json", ".json"));
-        }
-
-        // sometimes for jpg/png, the json file is .p.json or .j.json
-        if media_path.extension().unwrap().to_ascii_lowercase() == "jpg" {
-            // try pushing the same thing with numbers 1-20 appended
-            for i in 1..1000 {
-                if option.contains(format!("({})", i).as_str()) {
-                    // create a base without the .png.json and whatever (x) is appended
-                    let base = option.replace(".jpg.json", "").replace(".JPG.json", "");
-                    let length_in_chars = i.to_string().len();
-                    let base = base[..base.len() - length_in_chars - 2].to_string();
-
-                    let new_option = format!("{}.j({}).json", base, i);
-                    options.push(new_option);
-                }
-            }
+    /// Ask for confirmation of every fuzzy match while loading files. Answers are saved to `decisions_file`
+    /// and reused on later runs, so each match is only ever asked about once.
+    pub fn set_review_file(
+        &mut self,
+        decisions_file: impl AsRef<Path>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.decisions = Some(Decisions::load(decisions_file.as_ref())?);
+        Ok(())
+    }
 
-            let new_options = option
-                .replace(".jpg.json", ".j.json")
-                .replace(".JPG.json", ".j.json");
-            options.push(new_options);
-        }
-        if media_path.extension().unwrap().to_ascii_lowercase() == "png" {
-            // try pushing the same thing with numbers 1-20 appended
-            for i in 1..2000 {
-                if option.contains(format!("({})", i).as_str()) {
-                    // create a base without the .png.json and whatever (x) is appended
-                    let base = option.replace(".png.json", "").replace(".PNG.json", "");
-                    let length_in_chars = i.to_string().len();
-                    let base = base[..base.len() - length_in_chars - 2].to_string();
-
-                    let new_option = format!("{}.p({}).json", base, i);
-                    options.push(new_option);
-                }
-            }
+    pub fn set_config(&mut self, config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+        self.date_patterns = filename_date::with_custom(config.date_patterns()?);
+        Ok(())
+    }
 
-            let new_option = option
-                .replace(".png.json", ".p.json")
-                .replace(".PNG.json", ".p.json");
-            options.push(new_option);
-        }
+    /// The least precise date that is written into the EXIF, PNG and QuickTime date tags of a file without a
+    /// sidecar, `Day` by default. Less precise dates, e.g. a year from a folder name, are only written into
+    /// `XMP-photoshop:DateCreated`, which can hold part of a date, so they aren't mistaken for exact ones.
+    pub fn set_exif_precision(&mut self, precision: DatePrecision) {
+        self.exif_precision = precision;
     }
 
-    // if the file contains -edited try to remove it
-    for option in options.clone() {
-        options.push(option.replace("-edited", ""));
+    /// Estimate a date for files which have no other way of getting one, from the dated files around them in
+    /// their folder. This is off by default, as the dates are guesses.
+    pub fn set_interpolate(&mut self, interpolate: bool) {
+        self.interpolate = interpolate;
     }
 
-    // sometimes it's ..json instead of .json
-    for option in options.clone() {
-        options.push(option.replace("..json", ".json"));
-        options.push(option.replace("..", "."));
+    /// Choose how files that are in several albums are written out. By default every album gets its own copy.
+    pub fn set_album_output(&mut self, album_output: AlbumOutput) {
+        self.album_output = album_output;
     }
 
-    // try removing the last char of the filestem
-    for option in options.clone() {
-        let file_stem = Path::new(&option).file_stem().unwrap().to_str().unwrap();
-        let file_parent = Path::new(&option).parent().unwrap();
-        for i in 0..7 {
-            if file_stem.len() <= i {
-                break;
-            }
+    /// Leave out files an Immich server already has, by fetching the checksum of every asset on it. Their ids are
+    /// kept, so they are still added to their albums when uploading. Call this before `remove_duplicates`.
+    pub async fn skip_assets_in_immich(
+        &mut self,
+        server: &str,
+        api_key: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let assets = immich::Client::new(server, api_key).assets().await?;
+        println!("Found {} assets already in immich", assets.len());
+        self.immich_assets.extend(
+            assets
+                .into_iter()
+                .map(|(checksum, asset_id)| (checksum, Some(asset_id))),
+        );
+        Ok(())
+    }
 
-            // if the char is a ) or (, break
-            if file_stem.chars().rev().nth(i).unwrap() == ')' {
-                break;
+    /// Leave out files whose checksum is in a file exported from Immich, one per line. Call this before
+    /// `remove_duplicates`.
+    pub fn skip_checksums_in(&mut self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let checksums = immich::read_checksums(path)?;
+        println!(
+            "Read {} immich checksums from {}",
+            checksums.len(),
+            path.display()
+        );
+        self.immich_assets
+            .extend(checksums.into_iter().map(|checksum| (checksum, None)));
+        Ok(())
+    }
+
+    fn locale(&self) -> &'static Locale {
+        self.locale
+            .as_deref()
+            .and_then(locale::by_code)
+            .unwrap_or(&locale::LOCALES[0])
+    }
+
+    /// work out which language the export was made in from its folder and file names
+    fn detect_locale(&mut self) {
+        if self.locale.is_some() {
+            return;
+        }
+
+        let mut names = Vec::new();
+        for dir in self.takeout.directories() {
+            names.extend(dir.file_name().and_then(|name| name.to_str()));
+            for file in self.takeout.files_in(dir) {
+                names.extend(file.file_name().and_then(|name| name.to_str()));
             }
+        }
 
-            if let Some(res) = file_parent
-                .join(format!(
-                    "{}.json",
-                    &file_stem
-                        .chars()
-                        .take(file_stem.len() - i)
-                        .collect::<String>()
-                ))
-                .to_str()
-            {
-                options.push(res.to_string());
+        let detected = Locale::detect(names.into_iter());
+        println!("Detected takeout language: {}", detected.code);
+        self.locale = Some(detected.code.to_owned());
+    }
+
+    /// parse every json sidecar once, and index them by their name and the title of the media file they belong to
+    fn index_sidecars(&mut self) {
+        for json_path in self.json_files.iter() {
+            self.sidecars.insert_name(json_path);
+        }
+
+        let parsed: Vec<(PathBuf, Sidecar)> = self
+            .json_files
+            .par_iter()
+            .filter_map(|json_path| {
+                let contents = self.takeout.read_to_string(json_path).ok()?;
+                let sidecar = serde_json::from_str(&contents).ok()?;
+                Some((json_path.clone(), sidecar))
+            })
+            .collect();
+
+        println!("Indexed {} json sidecars by title", parsed.len());
+        for (json_path, sidecar) in parsed {
+            self.sidecars.insert(json_path, sidecar);
+        }
+    }
+
+    /// parse the metadata file of every album folder
+    fn index_albums(&mut self) {
+        for dir in self.takeout.directories() {
+            let Some(metadata_path) = self.takeout.files_in(dir).iter().find(|file| {
+                file.file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(locale::is_album_metadata)
+            }) else {
+                continue;
+            };
+
+            let album = self
+                .takeout
+                .read_to_string(metadata_path)
+                .map_err(|e| e.to_string())
+                .and_then(|contents| Album::parse(&contents).map_err(|e| e.to_string()));
+            match album {
+                Ok(album) => {
+                    self.albums.insert(dir.to_path_buf(), album);
+                }
+                Err(e) => println!(
+                    "Could not read album metadata {}: {}",
+                    metadata_path.display(),
+                    e
+                ),
             }
         }
+
+        println!("Found metadata for {} albums", self.albums.len());
     }
 
-    options.iter().map(PathBuf::from).collect()
-}
+    /// the album a media file is in, if its folder has album metadata
+    fn album_of(&self, media_path: &Path) -> Option<&Album> {
+        self.albums.get(media_path.parent()?)
+    }
 
-impl<'a> Processor<'_> {
-    pub fn new(takeout_directory: &'a str, output_directory: &'a str) -> Processor<'a> {
-        Processor {
-            takeout_directory,
-            output_directory,
-            media_files: Vec::new(),
-            json_files: HashSet::new(),
+    /// The output folder name of every album folder with media in it, owned or shared, from the title in its
+    /// metadata where it has one. Google strips and numbers folder names, and calls every shared album "Untitled",
+    /// so titles are used as they are, only made safe for any filesystem. Albums that still share a name are
+    /// numbered in the order of their takeout folders, which doesn't depend on the order files were found in.
+    fn album_folder_names(&self) -> HashMap<PathBuf, String> {
+        let locale = self.locale();
+        let folders: BTreeSet<&Path> = self
+            .media_files
+            .iter()
+            .filter_map(|media_file| media_file.media_path.parent())
+            .filter(|folder| {
+                folder
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| {
+                        !locale.is_archive_folder(name) && !locale.is_year_folder(name)
+                    })
+            })
+            .collect();
+
+        // compared without case, as on windows and macos
+        let mut taken = HashSet::new();
+        folders
+            .into_iter()
+            .map(|folder| {
+                let title = self
+                    .albums
+                    .get(folder)
+                    .map(|album| album.title.as_str())
+                    .filter(|title| !title.trim().is_empty())
+                    .or_else(|| folder.file_name().and_then(|name| name.to_str()))
+                    .unwrap_or_default();
+                let name = album::folder_name(title);
+
+                let mut unique = name.clone();
+                let mut counter = 2;
+                while !taken.insert(unique.to_lowercase()) {
+                    unique = format!("{} ({})", name, counter);
+                    counter += 1;
+                }
+                (folder.to_path_buf(), unique)
+            })
+            .collect()
+    }
+
+    /// sort a discovered file into the json or media lists, skipping anything we don't care about
+    fn register_file(&mut self, file_path: PathBuf) {
+        // skip if file is one of the locale's ignored files, or is album metadata
+        let file_name = file_path.file_name().unwrap().to_str().unwrap();
+        if is_ignored_file(file_name) {
+            return;
+        }
+
+        // skip if ext is in IGNORED_TYPES
+        if let Some(file_ext) = file_path.extension() {
+            if IGNORED_TYPES.contains(&file_ext.to_str().unwrap().to_lowercase().as_ref()) {
+                return;
+            }
+        }
+
+        if file_name.ends_with(".json") {
+            self.json_files.insert(file_path);
+        } else {
+            self.media_files.push(MediaFile {
+                media_path: file_path,
+                json_path: None,
+                destination_path: None,
+                destination_type: None,
+                media_creation_date: None,
+                match_source: MatchSource::NoMatch,
+                fuzzy_candidates: Vec::new(),
+                fuzzy_conflicts: Vec::new(),
+                albums: Vec::new(),
+                album_copies: Vec::new(),
+            });
         }
     }
 
-    /// recursively search through the takeout_directory, and find all media files/json files - load them into the processor
-    fn search_directory_recur(&mut self, path: PathBuf) -> Result<(), Box<dyn std::error::Error>> {
+    /// recursively search through an extracted takeout directory `root`, and find all media files/json files - load them into the processor
+    fn search_directory_recur(
+        &mut self,
+        root: &Path,
+        path: PathBuf,
+    ) -> Result<(), Box<dyn std::error::Error>> {
         for file in std::fs::read_dir(path)? {
             let file = file?;
 
             if file.file_type()?.is_dir() {
-                self.search_directory_recur(file.path())?;
+                self.search_directory_recur(root, file.path())?;
                 continue;
             }
 
-            // skip if file is in IGNORED_FILES
-            let file_name = file.file_name();
-            let file_name = file_name.to_str().unwrap();
-            if IGNORED_FILES.contains(&file_name.to_lowercase().as_ref()) {
-                continue;
+            if let Some(file_path) = self.takeout.add_file(root, file.path()) {
+                self.register_file(file_path);
             }
+        }
 
-            // skip if ext is in IGNORED_TYPES
-            if let Some(file_ext) = file.path().extension() {
-                if IGNORED_TYPES.contains(&file_ext.to_str().unwrap().to_lowercase().as_ref()) {
-                    continue;
-                }
-            }
+        Ok(())
+    }
 
-            let file_path = file.path();
-            let file_name = file_path.file_name().unwrap().to_str().unwrap();
-            if file_name.ends_with(".json") {
-                self.json_files.insert(file_path);
-            } else {
-                self.media_files.push(MediaFile {
-                    media_path: file_path,
-                    json_path: None,
-                    destination_path: None,
-                    destination_type: None,
-                    media_creation_date: None,
-                    match_source: MatchSource::NoMatch,
-                });
-            }
+    /// stream a takeout .tgz archive and load all of its entries into the processor, without extracting anything
+    fn search_tgz_archive(&mut self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        println!("Reading {}", path.display());
+        for file_path in self.takeout.add_tgz(path)? {
+            self.register_file(file_path);
+        }
+
+        Ok(())
+    }
+
+    /// list every entry of a takeout .zip archive and load them into the processor, without extracting anything
+    fn search_zip_archive(&mut self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        for file_path in self.takeout.add_zip(path)? {
+            self.register_file(file_path);
         }
 
         Ok(())
     }
 
-    /// for each media file which does NOT have a json file, try to pull date information from the filename, or failing that, the pathname
+    /// for each media file which does NOT have a json file, read the date it was taken from its own metadata, see
+    /// `embedded_date`
+    fn find_date_time_from_metadata(&mut self) {
+        let takeout = &self.takeout;
+        self.media_files
+            .par_iter_mut()
+            .filter(|file| matches!(file.match_source, MatchSource::NoMatch))
+            .for_each(|file| {
+                if let Ok(Some(found)) = takeout.embedded_date(&file.media_path) {
+                    file.media_creation_date = Some(found.date);
+                    file.match_source = MatchSource::Embedded {
+                        tag: found.tag.to_owned(),
+                    };
+                }
+            });
+    }
+
+    /// for each media file which does NOT have a json file or a date in its metadata, try to pull the date and time
+    /// it was taken from the filename, see `filename_date`
     fn find_date_time_from_filename(&mut self) -> Result<(), Box<dyn std::error::Error>> {
         for file in self.media_files.iter_mut() {
             if !matches!(file.match_source, MatchSource::NoMatch) {
                 continue;
             }
 
-            let mut file_name = file
-                .media_path
-                .file_stem()
-                .unwrap()
-                .to_str()
-                .unwrap()
-                .to_owned();
-
-            // replace (x) with nothing
-            for x in 0..5 {
-                file_name = file_name.replace(&format!("({})", x), "");
-            }
-            for replace_str in ["edited", "IMG", "VID", "JPEG", "EFFECTS"] {
-                for accent in ["-", "_"] {
-                    file_name = file_name.replace(&format!("{}{}", accent, replace_str), "");
-                    file_name = file_name.replace(&format!("{}{}", replace_str, accent), "");
-                }
+            let file_stem = file.media_path.file_stem().unwrap().to_str().unwrap();
+            if let Some(found) = filename_date::parse_with(&self.date_patterns, file_stem) {
+                file.media_creation_date = Some(found.date);
+                file.match_source = MatchSource::FileName {
+                    pattern: found.pattern,
+                    precision: found.precision,
+                };
             }
+        }
+        Ok(())
+    }
 
-            // if the filename is less than 6 characters long, it's not a valid date
-            if file_name.len() < 8 {
+    /// for each media file which has neither a json file nor a date in its name, try to read a date from the name
+    /// of the folder it is in, e.g. "Photos from 2014" or "2019-07-04 Beach trip"
+    fn find_date_time_from_directory(&mut self) {
+        for file in self.media_files.iter_mut() {
+            if !matches!(file.match_source, MatchSource::NoMatch) {
                 continue;
             }
 
-            // try to parse YYYYMMDD formats from the filename:
-            if let Some(date) = try_parse_8_char_date(&file_name) {
+            let Some(folder) = file
+                .media_path
+                .parent()
+                .and_then(|parent| parent.file_name())
+                .and_then(|folder| folder.to_str())
+            else {
+                continue;
+            };
+            let Some(folder_date) = folder_date::parse(folder) else {
+                continue;
+            };
+
+            let start =
+                NaiveDateTime::new(folder_date.start, NaiveTime::from_hms_opt(0, 0, 0).unwrap());
+            if let LocalResult::Single(date) = Local.from_local_datetime(&start) {
                 file.media_creation_date = Some(date);
-                file.match_source = MatchSource::FileName;
+                file.match_source = MatchSource::DirectoryName {
+                    folder: folder.to_owned(),
+                    precision: folder_date.precision,
+                    end: folder_date.end,
+                };
+            }
+        }
+    }
+
+    /// for each media file which has no date at all, fall back on the date its album was made
+    fn find_date_time_from_album(&mut self) {
+        for i in 0..self.media_files.len() {
+            if !matches!(self.media_files[i].match_source, MatchSource::NoMatch) {
                 continue;
             }
+            let Some(date) = self
+                .album_of(&self.media_files[i].media_path)
+                .and_then(Album::date)
+            else {
+                continue;
+            };
 
-            // try to parse YYYY-MM-DD and YYYY_MM_DD formats from the filename:
-            for accent in ["-", "_", " "] {
-                if let Some(date) = try_parse_8_char_date(&file_name.replace(accent, "")) {
-                    file.media_creation_date = Some(date);
-                    file.match_source = MatchSource::FileName;
-                    continue;
-                }
+            let file = &mut self.media_files[i];
+            file.media_creation_date = Some(date.with_timezone(&Local));
+            file.match_source = MatchSource::AlbumDate;
+        }
+    }
+
+    /// the date a media file is known to have been taken, precise enough to place the files around it by
+    fn known_date(&self, file: &MediaFile) -> Option<(DateTime<Local>, DatePrecision)> {
+        match &file.match_source {
+            MatchSource::JsonFile
+            | MatchSource::FuzzyMatch { .. }
+            | MatchSource::CrossDirectory { .. } => {
+                let taken_at = self.sidecars.get(file.json_path.as_ref()?)?.taken_at()?;
+                Some((taken_at.with_timezone(&Local), DatePrecision::Time))
+            }
+            MatchSource::Embedded { .. } => Some((file.media_creation_date?, DatePrecision::Time)),
+            MatchSource::FileName { precision, .. } if *precision >= DatePrecision::Day => {
+                Some((file.media_creation_date?, *precision))
             }
+            _ => None,
         }
-        Ok(())
     }
 
-    /// for each media file which does NOT have a json file or date information attached, try to fuzzy match the filename to a json file
-    fn fuzzy_match_filenames(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        let mut total_count = 0;
-        let count = AtomicUsize::new(0);
-        for file in self.media_files.iter() {
-            if matches!(file.match_source, MatchSource::NoMatch) {
-                total_count += 1;
+    /// for each media file which still has no date, or only a less precise one from its folder name, estimate one
+    /// from the dated files around it in the same folder, see `interpolate`
+    fn interpolate_dates(&mut self) {
+        let mut by_directory: BTreeMap<&Path, Vec<usize>> = BTreeMap::new();
+        for (i, file) in self.media_files.iter().enumerate() {
+            by_directory
+                .entry(file.media_path.parent().unwrap())
+                .or_default()
+                .push(i);
+        }
+
+        let mut estimates = Vec::new();
+        for indices in by_directory.values_mut() {
+            // takeout order is the order of the file names
+            indices.sort_by_key(|i| &self.media_files[*i].media_path);
+            let items: Vec<Item> = indices
+                .iter()
+                .map(|i| Item {
+                    file_stem: self.media_files[*i]
+                        .media_path
+                        .file_stem()
+                        .unwrap()
+                        .to_str()
+                        .unwrap(),
+                    date: self.known_date(&self.media_files[*i]),
+                })
+                .collect();
+            estimates.extend(indices.iter().copied().zip(interpolate::estimate(&items)));
+        }
+
+        for (i, estimate) in estimates {
+            let Some(estimate) = estimate else {
+                continue;
+            };
+            let file = &mut self.media_files[i];
+            let replaces = match &file.match_source {
+                MatchSource::NoMatch => true,
+                MatchSource::DirectoryName { precision, .. } => *precision < estimate.precision,
+                _ => false,
+            };
+            if replaces {
+                file.media_creation_date = Some(estimate.date);
+                file.match_source = MatchSource::Interpolated {
+                    confidence: estimate.confidence,
+                    precision: estimate.precision,
+                };
             }
         }
+    }
 
-        let directory_map: RwLock<HashMap<PathBuf, Arc<Vec<String>>>> = RwLock::new(HashMap::new());
+    /// Match the remaining media files to the remaining sidecars by how alike their names are.
+    ///
+    /// Every file is scored against the unclaimed sidecars in its directory, then the sidecars are handed out
+    /// best score first, so each sidecar goes to at most one file. A file that loses its best sidecar to a
+    /// better scoring file falls back to its runner-up, and the conflict is kept for the report.
+    fn fuzzy_match_filenames(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        // only sidecars nobody has claimed yet are up for grabs
+        let mut possible_matches: HashMap<&Path, Vec<&str>> = HashMap::new();
+        for json_path in self.json_files.iter() {
+            possible_matches
+                .entry(json_path.parent().unwrap())
+                .or_default()
+                .push(json_path.file_name().unwrap().to_str().unwrap());
+        }
+        // the set is unordered, so sort to settle ties the same way on every run
+        for names in possible_matches.values_mut() {
+            names.sort_unstable();
+        }
 
-        let new_media_files = RwLock::new(self.json_files.clone());
         self.media_files
             .par_iter_mut()
             .filter(|i| matches!(i.match_source, MatchSource::NoMatch))
             .for_each(|file| {
                 let file_name = file.media_path.file_name().unwrap().to_str().unwrap();
-
-                // load possible matches ALL json files in the same parent directory thare aren't in the IGNORED_FILES list
                 let parent_dir = file.media_path.parent().unwrap();
-
-                let contains_key = directory_map.read().unwrap().contains_key(parent_dir);
-                if !contains_key {
-                    let mut local_possible_matches = Vec::with_capacity(self.json_files.len());
-                    for file in std::fs::read_dir(parent_dir).unwrap() {
-                        let file = file.unwrap();
-                        if file.file_type().unwrap().is_dir() {
-                            continue;
-                        }
-                        let file_name = file.file_name();
-                        if IGNORED_FILES
-                            .contains(&file_name.to_str().unwrap().to_lowercase().as_ref())
-                        {
-                            continue;
-                        }
-                        if let Some(file_ext) = file.path().extension() {
-                            if IGNORED_TYPES
-                                .contains(&file_ext.to_str().unwrap().to_lowercase().as_ref())
-                            {
-                                continue;
-                            }
-                        } else {
-                            continue;
-                        }
-                        if file_name.to_str().unwrap().ends_with(".json") {
-                            local_possible_matches.push(file_name.to_str().unwrap().to_owned());
-                        }
-                    }
-                    directory_map
-                        .write()
-                        .unwrap()
-                        .insert(parent_dir.to_owned(), Arc::new(local_possible_matches));
-                }
-
-                let possible_matches = {
-                    let reader = directory_map.read().unwrap();
-                    reader.get(parent_dir).unwrap().clone()
+                let Some(names) = possible_matches.get(parent_dir) else {
+                    return;
                 };
 
-                let result = extract_one(
+                let mut candidates = extract_without_order(
                     file_name,
-                    possible_matches.as_ref(),
+                    names.iter(),
                     utils::full_process,
                     fuzz::wratio,
-                    90,
+                    FUZZY_ALTERNATIVE_SCORE,
                 );
+                // stable, so ties keep the directory order like `extract_one` does
+                candidates.sort_by(|(_, a), (_, b)| b.cmp(a));
+                candidates.truncate(FUZZY_MAX_CANDIDATES);
+                file.fuzzy_candidates = candidates
+                    .into_iter()
+                    .map(|(json_name, score)| FuzzyCandidate {
+                        json_path: parent_dir.join(json_name),
+                        score,
+                    })
+                    .collect();
+            });
 
-                if let Some((json_name, score)) = result {
-                    // find item in larger array, using both the expected parent directory, and the json file name
-                    let true_name = file.media_path.parent().unwrap().join(json_name);
-                    let res = new_media_files.read().unwrap().contains(&true_name);
-                    if res {
-                        new_media_files.write().unwrap().remove(&true_name);
-                    }
+        // every (file, sidecar) pair good enough to use, best first
+        let mut pairs: Vec<(u8, usize, PathBuf)> = Vec::new();
+        for (i, file) in self.media_files.iter().enumerate() {
+            for candidate in file.fuzzy_candidates.iter() {
+                if candidate.score >= FUZZY_MATCH_SCORE {
+                    pairs.push((candidate.score, i, candidate.json_path.clone()));
+                }
+            }
+        }
+        pairs.sort_by(|(a_score, a, a_json), (b_score, b, b_json)| {
+            b_score
+                .cmp(a_score)
+                .then_with(|| {
+                    self.media_files[*a]
+                        .media_path
+                        .cmp(&self.media_files[*b].media_path)
+                })
+                .then_with(|| a_json.cmp(b_json))
+        });
+
+        let mut taken_by: HashMap<PathBuf, usize> = HashMap::new();
+        let mut matched = HashSet::new();
+        for (score, i, json_path) in pairs {
+            if matched.contains(&i) {
+                continue;
+            }
+            if let Some(&winner) = taken_by.get(&json_path) {
+                let conflict = FuzzyConflict {
+                    json_path,
+                    score,
+                    taken_by: self.media_files[winner].media_path.clone(),
+                };
+                self.media_files[i].fuzzy_conflicts.push(conflict);
+                continue;
+            }
+
+            taken_by.insert(json_path.clone(), i);
+            matched.insert(i);
+            self.json_files.remove(&json_path);
+
+            let file = &mut self.media_files[i];
+            file.json_path = Some(json_path);
+            file.match_source = MatchSource::FuzzyMatch { score };
+        }
+
+        let total_count = self
+            .media_files
+            .iter()
+            .filter(|file| !file.fuzzy_candidates.is_empty())
+            .count();
+        let mut count = 0;
+        for file in self.media_files.iter() {
+            if file.fuzzy_candidates.is_empty() {
+                continue;
+            }
+            count += 1;
+            match (&file.json_path, &file.match_source) {
+                (Some(json_path), MatchSource::FuzzyMatch { score }) => {
                     println!(
                         "[{}/{}] Found match score[{}]: \n{}\n{}",
-                        count.fetch_add(1, Ordering::Relaxed),
+                        count,
                         total_count,
                         score,
-                        true_name.display(),
+                        json_path.display(),
                         file.media_path.display()
                     );
-                    file.json_path = Some(true_name);
-                    file.match_source = MatchSource::FuzzyMatch { score };
-                } else {
+                    for runner_up in file
+                        .fuzzy_candidates
+                        .iter()
+                        .filter(|candidate| &candidate.json_path != json_path)
+                    {
+                        println!(
+                            "    runner-up score[{}]: {}",
+                            runner_up.score,
+                            runner_up.json_path.display()
+                        );
+                    }
+                }
+                _ if !file.fuzzy_conflicts.is_empty() => {
                     println!(
-                        "[{}/{}] No match found for:\n{}",
-                        count.fetch_add(1, Ordering::Relaxed),
+                        "[{}/{}] Every match went to a better scoring file for:\n{}",
+                        count,
                         total_count,
                         file.media_path.display()
                     );
+                    for conflict in file.fuzzy_conflicts.iter() {
+                        println!(
+                            "    score[{}]: {} taken by {}",
+                            conflict.score,
+                            conflict.json_path.display(),
+                            conflict.taken_by.display()
+                        );
+                    }
                 }
-            });
+                _ => println!(
+                    "[{}/{}] No match found for:\n{}",
+                    count,
+                    total_count,
+                    file.media_path.display()
+                ),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Last resort for files whose sidecar ended up in another directory, usually an album copy of a photo
+    /// whose only sidecar is in "Photos from YYYY". Unmatched sidecars anywhere in the export are looked up by
+    /// title, and if the file name holds a date, it has to agree with when the sidecar says the photo was taken.
+    /// Several copies of a photo in different albums may share the one sidecar.
+    fn match_across_directories(&mut self) {
+        let mut claimed = Vec::new();
+        for file in self.media_files.iter_mut() {
+            if !matches!(file.match_source, MatchSource::NoMatch) {
+                continue;
+            }
+
+            let candidates: Vec<&PathBuf> = self
+                .sidecars
+                .find_by_title(&file.media_path)
+                .into_iter()
+                .filter(|json_path| self.json_files.contains(*json_path))
+                .collect();
+            if candidates.is_empty() {
+                continue;
+            }
+
+            let file_stem = file.media_path.file_stem().unwrap().to_str().unwrap();
+            let (json_path, timestamp) =
+                match filename_date::parse_with(&self.date_patterns, file_stem) {
+                    Some(FilenameDate {
+                        date: name_date, ..
+                    }) => {
+                        // allow a day either side, as the sidecar is in UTC and the file name in local time
+                        let agreeing: Vec<&PathBuf> = candidates
+                            .into_iter()
+                            .filter(|json_path| {
+                                self.sidecars
+                                    .get(json_path)
+                                    .and_then(|sidecar| sidecar.taken_at())
+                                    .is_some_and(|taken_at| {
+                                        (taken_at.date_naive() - name_date.date_naive())
+                                            .num_days()
+                                            .abs()
+                                            <= 1
+                                    })
+                            })
+                            .collect();
+                        match agreeing[..] {
+                            [json_path] => (json_path, true),
+                            _ => {
+                                println!(
+                                "Could not pick a sidecar in another directory for {:?}, skipping",
+                                file.media_path
+                            );
+                                continue;
+                            }
+                        }
+                    }
+                    None => match candidates[..] {
+                        [json_path] => (json_path, false),
+                        _ => {
+                            println!(
+                                "Could not pick a sidecar in another directory for {:?}, skipping",
+                                file.media_path
+                            );
+                            continue;
+                        }
+                    },
+                };
+
+            file.json_path = Some(json_path.clone());
+            file.match_source = MatchSource::CrossDirectory { timestamp };
+            claimed.push(json_path.clone());
+        }
+
+        for json_path in claimed {
+            self.json_files.remove(&json_path);
+        }
+    }
+
+    /// Go through every fuzzy match, asking the user to accept it, reject it or pick one of the alternatives.
+    /// Matches answered on a previous run are settled from the decisions file without asking. A file whose
+    /// sidecar is picked for another file is asked about again, and a sidecar already matched by name can't be
+    /// picked.
+    pub fn review_fuzzy_matches(
+        &mut self,
+        input: &mut dyn BufRead,
+        output: &mut dyn Write,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(decisions) = self.decisions.as_mut() else {
+            return Err("No review file set, see `set_review_file`".into());
+        };
+
+        // files that lost every sidecar to better scoring files need a human to look at them most of all
+        let mut to_review: VecDeque<usize> = (0..self.media_files.len())
+            .filter(|&i| {
+                let file = &self.media_files[i];
+                match file.match_source {
+                    MatchSource::FuzzyMatch { .. } => true,
+                    MatchSource::NoMatch => !file.fuzzy_conflicts.is_empty(),
+                    _ => false,
+                }
+            })
+            .collect();
+
+        let mut reviewed = 0;
+        while let Some(i) = to_review.pop_front() {
+            reviewed += 1;
+
+            // a saved answer is only reused while the sidecar it picked still exists and hasn't since been matched
+            // to another file, e.g. by its name in a newer takeout
+            let decision = match decisions.get(&self.media_files[i].media_path) {
+                Some(Decision::Accepted { json_path })
+                    if !self.takeout.exists(json_path)
+                        || held_by_match(&self.media_files, json_path).is_some() =>
+                {
+                    None
+                }
+                decision => decision.cloned(),
+            };
+            let decision = match decision {
+                Some(decision) => decision,
+                None => loop {
+                    let file = &self.media_files[i];
+                    let proposed = file.json_path.as_ref().and_then(|json_path| {
+                        file.fuzzy_candidates
+                            .iter()
+                            .position(|candidate| &candidate.json_path == json_path)
+                    });
+                    let decision = review::ask(
+                        input,
+                        output,
+                        (reviewed, reviewed + to_review.len()),
+                        &file.media_path,
+                        &file.fuzzy_candidates,
+                        proposed,
+                    )?;
+                    if let Decision::Accepted { json_path } = &decision {
+                        if let Some(holder) = held_by_match(&self.media_files, json_path) {
+                            writeln!(
+                                output,
+                                "{} is already the sidecar of {}, pick another",
+                                json_path.display(),
+                                holder.display()
+                            )?;
+                            continue;
+                        }
+                    }
+                    decisions.record(file.media_path.clone(), decision.clone())?;
+                    break decision;
+                },
+            };
 
-        self.json_files = new_media_files.into_inner().unwrap();
+            let file = &mut self.media_files[i];
+            // hand the proposed sidecar back, so it can be used by another file
+            if let Some(previous) = file.json_path.take() {
+                self.json_files.insert(previous);
+            }
+            match decision {
+                Decision::Accepted { json_path } => {
+                    let score = file
+                        .fuzzy_candidates
+                        .iter()
+                        .find(|candidate| candidate.json_path == json_path)
+                        .map_or(0, |candidate| candidate.score);
+                    file.json_path = Some(json_path.clone());
+                    file.match_source = MatchSource::FuzzyMatch { score };
+
+                    // the user's pick wins over a fuzzy match of another file to the same sidecar, and that file
+                    // is asked about again, as whatever was decided about it before no longer holds
+                    if !self.json_files.remove(&json_path) {
+                        let media_path = self.media_files[i].media_path.clone();
+                        for (j, other) in self.media_files.iter_mut().enumerate() {
+                            if j != i
+                                && matches!(other.match_source, MatchSource::FuzzyMatch { .. })
+                                && other.json_path.as_ref() == Some(&json_path)
+                            {
+                                writeln!(
+                                    output,
+                                    "{} lost {} to {}, it will be asked about again",
+                                    other.media_path.display(),
+                                    json_path.display(),
+                                    media_path.display()
+                                )?;
+                                let score = other
+                                    .fuzzy_candidates
+                                    .iter()
+                                    .find(|candidate| candidate.json_path == json_path)
+                                    .map_or(0, |candidate| candidate.score);
+                                other.fuzzy_conflicts.push(FuzzyConflict {
+                                    json_path: json_path.clone(),
+                                    score,
+                                    taken_by: media_path.clone(),
+                                });
+                                other.json_path = None;
+                                other.match_source = MatchSource::NoMatch;
+                                decisions.remove(&other.media_path)?;
+                                if !to_review.contains(&j) {
+                                    to_review.push_back(j);
+                                }
+                            }
+                        }
+                    }
+                }
+                Decision::Rejected => file.match_source = MatchSource::NoMatch,
+            }
+        }
 
         Ok(())
     }
@@ -432,14 +1286,13 @@ impl<'a> Processor<'_> {
         // To find teh json file of a given file, follow the steps:
         // 0. If the file is in the exclusion HashSet, skip it.
         // 1. If the file is of type json, skip it.
-        // 2. If the file has ` (x)` where x is a number appended to the end:
-        //  e.g. take the filename, strip the last (2+size_of_int_in_chars) from the filestem
-        //  readd the file ext
-        //  Append ` (x).json`
-        // 3. If the file is of type heic, the json file has NO extension, otherwise it's as usual:
+        // 2. Look the file up by the title stored in every sidecar in the same directory
+        // 3. Failing that, look it up by sidecar name, with `.json`, `(x)` and `.supplemental-metadata` stripped:
         //  - heic: filename.heic -> filename.json
-        //  - non-heic: filename.jpg -> filename.jpg.josn
-        // 4. Attempt to find the json file
+        //  - non-heic: filename.jpg -> filename.jpg.json
+        //  - duplicates: filename(x).jpg -> filename.jpg(x).json
+        // 4. Failing that, allow for google cutting long names short, 46 characters for sidecars and 47 for media
+        // All of these are lookups in the sidecar index, so nothing here touches the disk
         // 5. If json file found AND input file is .heic, add item to exclusion hashset for extra .mp4 (if present)
         let mut exclusion = HashMap::new();
         for file in self.media_files.iter_mut() {
@@ -454,39 +1307,44 @@ impl<'a> Processor<'_> {
 
             // If file is a json file, skip it
             if file.media_path.extension().is_none()
-                || file.media_path.extension().unwrap().to_ascii_lowercase() == "json"
+                || file
+                    .media_path
+                    .extension()
+                    .unwrap()
+                    .eq_ignore_ascii_case("json")
             {
                 continue;
             }
 
-            // 4. Attempt to find the json file
-            let potential_paths = json_path_from_media_path(&file.media_path);
-
-            for potential_path in potential_paths {
+            // 2-4. Look the file up by title, and then by the json file names, without touching the disk
+            if let Some(json_path) = self.sidecars.resolve(&file.media_path).cloned() {
                 // remove json file from json_files HashSet
-                if self.json_files.contains(&potential_path) {
-                    self.json_files.remove(&potential_path);
-                }
+                self.json_files.remove(&json_path);
 
-                if potential_path.exists() {
-                    file.json_path = Some(potential_path.to_path_buf());
-                    file.match_source = MatchSource::JsonFile;
+                file.json_path = Some(json_path);
+                file.match_source = MatchSource::JsonFile;
 
-                    // 5. If json file found AND input file is .heic, add item to exclusion hashset for extra .mp4 (if present)
-                    // sometimes this also applies to .JPG files?
-                    if file.media_path.extension().unwrap().to_ascii_lowercase() == "heic"
-                        || file.media_path.extension().unwrap().to_ascii_lowercase() == "jpg"
-                    {
-                        let mp4_path = file.media_path.with_extension("MP4");
-                        if mp4_path.exists() {
-                            exclusion.insert(mp4_path, file.json_path.clone());
-                        }
-                        let mp4_path = file.media_path.with_extension("mp4");
-                        if mp4_path.exists() {
-                            exclusion.insert(mp4_path, file.json_path.clone());
-                        }
+                // 5. If json file found AND input file is .heic, add item to exclusion hashset for extra .mp4 (if present)
+                // sometimes this also applies to .JPG files?
+                if file
+                    .media_path
+                    .extension()
+                    .unwrap()
+                    .eq_ignore_ascii_case("heic")
+                    || file
+                        .media_path
+                        .extension()
+                        .unwrap()
+                        .eq_ignore_ascii_case("jpg")
+                {
+                    let mp4_path = file.media_path.with_extension("MP4");
+                    if self.takeout.exists(&mp4_path) {
+                        exclusion.insert(mp4_path, file.json_path.clone());
+                    }
+                    let mp4_path = file.media_path.with_extension("mp4");
+                    if self.takeout.exists(&mp4_path) {
+                        exclusion.insert(mp4_path, file.json_path.clone());
                     }
-                    break;
                 }
             }
         }
@@ -510,15 +1368,58 @@ impl<'a> Processor<'_> {
     }
 
     pub fn load_files(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        self.search_directory_recur(self.takeout_directory.into())?;
+        for input in self.inputs.clone() {
+            if input.is_dir() {
+                self.search_directory_recur(&input, input.clone())?;
+            } else if input
+                .extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("zip"))
+            {
+                self.search_zip_archive(&input)?;
+            } else if input
+                .extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("tgz"))
+                || input.to_string_lossy().to_lowercase().ends_with(".tar.gz")
+            {
+                self.search_tgz_archive(&input)?;
+            } else {
+                return Err(format!("Unsupported takeout input: {}", input.display()).into());
+            }
+        }
+
+        self.detect_locale();
+
+        self.index_sidecars();
+
+        self.index_albums();
 
         self.match_json_files_to_media_files()?;
 
         self.fuzzy_match_filenames()?;
 
-        // Try to load date/times from filenames
+        if self.decisions.is_some() {
+            self.review_fuzzy_matches(&mut std::io::stdin().lock(), &mut std::io::stdout())?;
+        }
+
+        self.match_across_directories();
+
+        // Try to load date/times from the files themselves
+        self.find_date_time_from_metadata();
+
+        // Failing that, from their names
         self.find_date_time_from_filename()?;
 
+        // Failing that, from the folder they are in
+        self.find_date_time_from_directory();
+
+        // and if asked, from the files around them
+        if self.interpolate {
+            self.interpolate_dates();
+        }
+
+        // and as a last resort, from their album
+        self.find_date_time_from_album();
+
         println!("Number of unmatched json files: {}", self.json_files.len());
         println!(
             "Number of unmatched media files: {}",
@@ -530,22 +1431,53 @@ impl<'a> Processor<'_> {
 
         let mut no_match = 0;
         let mut json_file = 0;
+        let mut embedded = 0;
         let mut file_name = 0;
         let mut directory_name = 0;
         let mut fuzzy_match = 0;
+        let mut cross_directory = 0;
+        let mut interpolated = 0;
+        let mut album_date = 0;
         for file in self.media_files.iter() {
             match file.match_source {
                 MatchSource::NoMatch => no_match += 1,
                 MatchSource::JsonFile => json_file += 1,
-                MatchSource::FileName => file_name += 1,
-                MatchSource::DirectoryName => directory_name += 1,
+                MatchSource::Embedded { .. } => embedded += 1,
+                MatchSource::FileName { .. } => file_name += 1,
+                MatchSource::DirectoryName { .. } => directory_name += 1,
                 MatchSource::FuzzyMatch { .. } => fuzzy_match += 1,
+                MatchSource::CrossDirectory { .. } => cross_directory += 1,
+                MatchSource::Interpolated { .. } => interpolated += 1,
+                MatchSource::AlbumDate => album_date += 1,
             }
         }
         println!("Matched by json file: {}", json_file);
+        println!("Matched by embedded date: {}", embedded);
         println!("Matched by file name: {}", file_name);
+        let mut by_pattern: BTreeMap<&str, usize> = BTreeMap::new();
+        for file in self.media_files.iter() {
+            if let MatchSource::FileName { pattern, .. } = &file.match_source {
+                *by_pattern.entry(pattern).or_default() += 1;
+            }
+        }
+        for (pattern, count) in by_pattern {
+            println!("    with pattern `{}`: {}", pattern, count);
+        }
         println!("Matched by directory name: {}", directory_name);
         println!("Matched by fuzzy match: {}", fuzzy_match);
+        println!("Estimated from neighbouring files: {}", interpolated);
+        println!("Dated by their album: {}", album_date);
+        println!(
+            "Matched by json file in another directory: {}",
+            cross_directory
+        );
+        println!(
+            "Fuzzy match conflicts: {}",
+            self.media_files
+                .iter()
+                .map(|x| x.fuzzy_conflicts.len())
+                .sum::<usize>()
+        );
         println!("No match: {}", no_match);
 
         // print all unmatched json files
@@ -562,6 +1494,15 @@ impl<'a> Processor<'_> {
             println!("Could not find match for json file: {:?}", file);
         }
 
+        for file in self.media_files.iter() {
+            for conflict in file.fuzzy_conflicts.iter() {
+                println!(
+                    "Fuzzy match conflict: {:?} score[{}] for {:?} was taken by {:?}",
+                    conflict.json_path, conflict.score, file.media_path, conflict.taken_by
+                );
+            }
+        }
+
         Ok(())
     }
 
@@ -574,140 +1515,95 @@ impl<'a> Processor<'_> {
         // Based on their path, they should be moved in to one of these three directories.
         // Photos in a folder named "Archive" -> General Photos
         // Photos in a folder named "Photos from YYYY" where YYYY is a year -> General Photos
-        // Photos in a folder named "Untitled" or "Untitled(x)" where x is an integer -> Shared Albums
+        // Photos in a folder named "Untitled" or "Untitled(x)" where x is an integer -> Shared Albums (by album title)
         // Photos in any other folders -> Album (preserve folder structure)
+        // The folder names are translated into the language of the export.
         let mut extension_mismatch_count = 0;
+        let locale = self.locale();
+        let album_folders = self.album_folder_names();
+        self.album_sources = album_folders
+            .iter()
+            .map(|(folder, name)| (name.clone(), folder.clone()))
+            .collect();
 
         // Iterate through all files and sort based on their path
         for media_file in self.media_files.iter_mut() {
             let file_path = &media_file.media_path;
             let file_parent = file_path.parent().unwrap();
-            let file_parent_name = file_parent.file_name().unwrap().to_str().unwrap();
+            let file_parent_name = file_parent
+                .file_name()
+                .map_or("", |name| name.to_str().unwrap());
 
             let general_photos: PathBuf =
                 PathBuf::from(format!("{}/general", self.output_directory));
             let albums: PathBuf = PathBuf::from(format!("{}/albums", self.output_directory));
-            let shared_albums: PathBuf =
-                PathBuf::from(format!("{}/shared/shared", self.output_directory));
+            let shared_albums: PathBuf = PathBuf::from(format!("{}/shared", self.output_directory));
 
             match file_parent_name {
-                "Archive" => {
+                // files sitting directly in the root of the takeout aren't in any album
+                "" => {
                     media_file.destination_path =
                         Some(general_photos.join(file_path.file_name().unwrap()));
                     media_file.destination_type = Some(DestLocation::General);
                 }
-                x if x.starts_with("Photos from ") && x.len() == "Photos from 2000".len() => {
+                x if locale.is_archive_folder(x) => {
                     media_file.destination_path =
                         Some(general_photos.join(file_path.file_name().unwrap()));
                     media_file.destination_type = Some(DestLocation::General);
                 }
-                "Untitled" => {
+                x if locale.is_year_folder(x) => {
                     media_file.destination_path =
-                        Some(shared_albums.join(file_path.file_name().unwrap()));
-                    media_file.destination_type = Some(DestLocation::Shared);
+                        Some(general_photos.join(file_path.file_name().unwrap()));
+                    media_file.destination_type = Some(DestLocation::General);
                 }
-                x if x.starts_with("Untitled(") => {
-                    media_file.destination_path =
-                        Some(shared_albums.join(file_path.file_name().unwrap()));
-                    media_file.destination_type = Some(DestLocation::Shared);
+                x if locale.is_untitled_folder(x) => {
+                    let album = album_folders[file_parent].clone();
+                    media_file.destination_path = Some(
+                        shared_albums
+                            .join(&album)
+                            .join(file_path.file_name().unwrap()),
+                    );
+                    media_file.destination_type = Some(DestLocation::Shared { album });
                 }
                 _ => {
-                    // include only the parent directory and the file name
-                    let mut destination_path = PathBuf::from(file_parent_name);
-                    destination_path.push(file_path.file_name().unwrap());
-                    media_file.destination_path = Some(albums.join(destination_path));
-                    media_file.destination_type = Some(DestLocation::Albums);
+                    // include only the album and the file name
+                    let album = album_folders[file_parent].clone();
+                    media_file.destination_path =
+                        Some(albums.join(&album).join(file_path.file_name().unwrap()));
+                    media_file.destination_type = Some(DestLocation::Albums { album });
                 }
             }
 
             let dest_path = media_file.destination_path.as_ref().unwrap();
 
-            // update the destination path with the correct extension
-            // skip incompatible extensions
-            if dest_path.extension().is_some() && (dest_path.extension().unwrap() == "MTS") {
-                continue;
-            }
-
-            // use the unix "file" command to determine the file type if a file has no ext
-            let file_type = Command::new("file")
-                .arg(file_path)
-                .output()
-                .expect("failed to execute process");
-            let file_type = String::from_utf8_lossy(&file_type.stdout);
-            let file_type = file_type.to_string().to_ascii_lowercase();
-            let file_type = file_type.split(':').into_iter().nth(1).unwrap();
-
-            // println!("{}: {}", file_path.display(), file_type);
-
-            let dest_file_ext = {
-                if file_type.contains("png image data") {
-                    "png"
-                } else if file_type.contains("jpg image data")
-                    || file_type.contains("jpeg image data")
-                {
-                    "jpg"
-                } else if file_type.contains("gif image data") {
-                    "gif"
-                } else if file_type.contains("heic image data")
-                    || file_type.contains("iso media, heif image hevc main")
-                {
-                    "heic"
-                } else if file_type.contains("mp3 audio") {
-                    "mp3"
-                } else if file_type.contains("apple quicktime movie") {
-                    "mov"
-                } else if file_type.contains("mp4 video")
-                    || file_type.contains("iso media, mp4 v")
-                    || file_type.contains("iso media, mp4 base media v")
-                    || file_type.contains("iso media, mpeg-4")
-                    || file_type.contains("iso media, mpeg v")
-                {
-                    "mp4"
-                } else if file_type.contains("mov video") {
-                    "mov"
-                } else if file_type.contains("3gp video") {
-                    "3gp"
-                } else if file_type.contains("tiff image data") {
-                    "tiff"
-                } else if file_type.contains("pc bitmap") {
-                    "bmp"
-                } else if file_type.contains("apple itunes video (.m4v)") {
-                    "m4v"
-                } else if file_type.contains("web/p image") {
-                    "webp"
-                } else if file_type.contains("microsoft asf") {
-                    "asf"
-                } else if file_type.contains("mpeg sequence") {
-                    "mpeg"
-                } else if file_type.contains("avi") {
-                    "avi"
-                } else if file_type.contains("canon cr2") {
-                    "cr2"
-                } else if file_type.trim() == "data"
-                    || file_type.contains("ascii text")
-                    || file_type.contains("canon ciff raw image data")
-                {
-                    // wtf is this? return the original extension
-                    dest_path.extension().unwrap().to_str().unwrap()
-                } else {
-                    panic!(
-                        "Unknown file type: `{}` while processing file `{}`",
-                        file_type,
-                        file_path.display()
-                    );
+            // update the destination path with the extension of what the file really is, leaving right ones as
+            // they are, whatever their case
+            // a file that can't be read keeps its extension, and is skipped when duplicates are removed
+            let header = match self.takeout.read_header(file_path, file_type::HEADER_LEN) {
+                Ok(header) => header,
+                Err(e) => {
+                    println!("Could not read {}: {}", file_path.display(), e);
+                    continue;
                 }
             };
-
-            // if they aren't the same, increment a counter
-            if dest_path.extension().is_none()
-                || dest_path.extension().unwrap().to_ascii_lowercase() != dest_file_ext
-            {
-                extension_mismatch_count += 1;
-                println!("Extension mismatch: {:?} -> {:?}", dest_path, dest_file_ext)
+            // not a format we know, so the original extension is as good a guess as any
+            let Some(file_type) = FileType::detect(&header) else {
+                continue;
+            };
+            let current_ext = dest_path.extension().and_then(|ext| ext.to_str());
+            if current_ext.is_some_and(|ext| file_type.matches_extension(ext)) {
+                continue;
             }
 
+            extension_mismatch_count += 1;
+            println!(
+                "Extension mismatch: {:?} -> {:?}",
+                dest_path,
+                file_type.extension()
+            );
+
             let mut new_dest_path = dest_path.clone();
-            new_dest_path.set_extension(dest_file_ext);
+            new_dest_path.set_extension(file_type.extension());
             media_file.destination_path = Some(new_dest_path);
         }
 
@@ -732,13 +1628,16 @@ impl<'a> Processor<'_> {
         // we want to remove files in a specific priority to preserve file structure in albums
         // 1. Files in the "general" directory are removed first
         // 2. Files in the "shared" directory are removed next
-        // 3. Files in the "albums" directory are never removed.
+        // 3. Files in the "albums" directory are removed last, and only in favour of another album
+        // The file that is kept remembers every album the removed ones were in.
+        // Files immich already has are all removed, whatever their destination.
 
         println!("Removing duplicate files");
 
         let mut counter = 0;
         let total_files = self.media_files.len();
-        let mut files: HashMap<String, Vec<&MediaFile>> = HashMap::new();
+        let mut hashes: Vec<io::Result<FileHashes>> = Vec::with_capacity(total_files);
+        let takeout = &self.takeout;
         for chunk in self.media_files.chunks(1024) {
             let mut futures = Vec::with_capacity(1024);
 
@@ -747,81 +1646,155 @@ impl<'a> Processor<'_> {
                 counter += 1;
 
                 futures.push(async move {
-                    let hash_file_name = media_file.media_path.with_extension("hash");
-
-                    // if there exists a hash file already, just grab the hash from that
-                    if hash_file_name.exists() {
-                        let mut hash_file = tokio::fs::File::open(&hash_file_name).await.unwrap();
-                        let mut hash = String::new();
-                        hash_file.read_to_string(&mut hash).await.unwrap();
-                        return hash;
+                    // .tgz entries were already hashed while they were being discovered
+                    if let Some(hashes) = takeout.cached_hashes(&media_file.media_path) {
+                        return Ok(hashes.clone());
                     }
 
-                    let mut hasher = Sha3_256::new();
-                    let mut file = tokio::fs::File::open(&media_file.media_path).await.unwrap();
+                    // archive entries can't have a hash file stored next to them, so hash them straight out of the archive
+                    let Some(real_path) = takeout.real_path(&media_file.media_path) else {
+                        let hashes = takeout.with_reader(&media_file.media_path, hash_reader)?;
+
+                        println!("Hashing file {}/{}", num, total_files);
+                        return Ok(hashes);
+                    };
+
+                    let hash_file_name = real_path.with_extension("hash");
+
+                    // if there exists a hash file already, just grab the hashes from that, older ones only have the
+                    // sha3 so are hashed again
+                    if let Ok(cached) = tokio::fs::read_to_string(&hash_file_name).await {
+                        if let Some((sha3, sha1)) = cached.split_once('\n') {
+                            return Ok(FileHashes {
+                                sha3: sha3.to_owned(),
+                                sha1: sha1.to_owned(),
+                            });
+                        }
+                    }
+
+                    let mut sha3 = Sha3_256::new();
+                    let mut sha1 = Sha1::new();
+                    let mut file = tokio::fs::File::open(real_path).await?;
                     let mut buf = [0; 1024];
                     loop {
-                        let n = file.read(&mut buf).await.unwrap();
+                        let n = file.read(&mut buf).await?;
                         if n == 0 {
                             break;
                         }
-                        hasher.update(&buf[..n]);
+                        sha3.update(&buf[..n]);
+                        sha1.update(&buf[..n]);
                     }
 
                     println!("Hashing file {}/{}", num, total_files);
 
-                    let hash = hasher.finalize_reset();
-                    let hash = format!("{:x}", hash);
+                    let hashes = FileHashes {
+                        sha3: format!("{:x}", sha3.finalize()),
+                        sha1: format!("{:x}", sha1.finalize()),
+                    };
 
-                    // save the hash to a file NEXT to the file
-                    let mut hash_file = tokio::fs::File::create(&hash_file_name).await.unwrap();
-                    hash_file.write_all(hash.as_bytes()).await.unwrap();
+                    // save the hashes to a file NEXT to the file, which only saves time on the next run so isn't
+                    // worth stopping for
+                    let contents = format!("{}\n{}", hashes.sha3, hashes.sha1);
+                    if let Err(e) = tokio::fs::write(&hash_file_name, contents).await {
+                        println!("Could not save {}: {}", hash_file_name.display(), e);
+                    }
 
-                    hash
+                    Ok(hashes)
                 });
             }
 
-            let hashes = futures::future::join_all(futures).await;
-
-            for (i, hash) in hashes.iter().enumerate() {
-                let media_file = &self.media_files[i];
-                let files = files.entry(hash.to_string()).or_default();
-                files.push(media_file);
-            }
+            hashes.extend(futures::future::join_all(futures).await);
         }
 
-        files.retain(|_, v| v.len() > 1);
-
-        // for any arrays > 1 element, we need to remove the duplicates
-        for (_, files) in files.iter_mut() {
-            // sort the files by their destination path
-            // albums -> shared -> general
-            files.sort_by(|a, b| {
-                match (a.destination_type.unwrap(), b.destination_type.unwrap()) {
-                    (DestLocation::Albums, _) => std::cmp::Ordering::Less,
-                    (_, DestLocation::Albums) => std::cmp::Ordering::Greater,
-                    (DestLocation::Shared, _) => std::cmp::Ordering::Less,
-                    (_, DestLocation::Shared) => std::cmp::Ordering::Greater,
-                    _ => std::cmp::Ordering::Equal,
+        // a file that can't be read is left out rather than losing the rest of the run to it
+        let mut to_be_removed: HashSet<usize> = HashSet::new();
+        let mut files: HashMap<&str, Vec<usize>> = HashMap::new();
+        for (i, file_hashes) in hashes.iter().enumerate() {
+            match file_hashes {
+                Ok(file_hashes) => files.entry(&file_hashes.sha3).or_default().push(i),
+                Err(e) => {
+                    let media_path = self.media_files[i].media_path.clone();
+                    println!("Could not read {}, skipping: {}", media_path.display(), e);
+                    self.skipped.push(SkippedFile {
+                        media_path,
+                        reason: SkipReason::Unreadable {
+                            error: e.to_string(),
+                        },
+                    });
+                    to_be_removed.insert(i);
                 }
+            }
+        }
+        let unreadable = to_be_removed.len();
+
+        let mut in_several_albums = 0;
+        let mut in_immich = 0;
+        for (_, mut duplicates) in files {
+            // sort the files by their destination, albums -> shared -> general, then by path so the same one is
+            // always kept
+            duplicates.sort_by(|a, b| {
+                let (a, b) = (&self.media_files[*a], &self.media_files[*b]);
+                let a_rank = a.destination_type.as_ref().unwrap().keep_rank();
+                let b_rank = b.destination_type.as_ref().unwrap().keep_rank();
+                (a_rank, &a.media_path).cmp(&(b_rank, &b.media_path))
             });
 
-            // remove the first item
-            files.remove(0);
-        }
+            // immich already has it, so none of them need copying
+            let sha1 = &hashes[duplicates[0]].as_ref().unwrap().sha1;
+            if let Some(asset_id) = self.immich_assets.get(sha1) {
+                for i in &duplicates {
+                    let file = &self.media_files[*i];
+                    self.skipped.push(SkippedFile {
+                        media_path: file.media_path.clone(),
+                        reason: SkipReason::InImmich {
+                            asset_id: asset_id.clone(),
+                            album: file.destination_type.as_ref().unwrap().membership(),
+                        },
+                    });
+                }
+                in_immich += duplicates.len();
+                to_be_removed.extend(&duplicates);
+                continue;
+            }
 
-        // remove all files from the files array, comparing on the source path
-        let mut to_be_removed: HashSet<String> = HashSet::new();
+            let mut albums: Vec<AlbumMembership> = Vec::new();
+            for i in &duplicates {
+                let destination = self.media_files[*i].destination_type.as_ref().unwrap();
+                if let Some(album) = destination.membership() {
+                    if !albums.contains(&album) {
+                        albums.push(album);
+                    }
+                }
+            }
+            if albums.len() > 1 {
+                in_several_albums += 1;
+            }
 
-        for (_, files) in files.iter() {
-            for file in files {
-                to_be_removed.insert(file.media_path.display().to_string());
+            // keep the first item
+            self.media_files[duplicates[0]].albums = albums;
+            for i in &duplicates[1..] {
+                self.skipped.push(SkippedFile {
+                    media_path: self.media_files[*i].media_path.clone(),
+                    reason: SkipReason::Duplicate {
+                        kept: self.media_files[duplicates[0]].media_path.clone(),
+                    },
+                });
             }
+            to_be_removed.extend(&duplicates[1..]);
         }
 
-        println!("Removing {} duplicate files from array", to_be_removed.len());
-        self.media_files
-            .retain(|f| !to_be_removed.contains(&f.media_path.display().to_string()));
+        println!("Skipping {} files that couldn't be read", unreadable);
+        println!("Skipping {} files immich already has", in_immich);
+        println!(
+            "Removing {} duplicate files from array",
+            to_be_removed.len() - in_immich - unreadable
+        );
+        println!("{} files are in more than one album", in_several_albums);
+        let mut index = 0;
+        self.media_files.retain(|_| {
+            index += 1;
+            !to_be_removed.contains(&(index - 1))
+        });
 
         Ok(())
     }
@@ -831,9 +1804,16 @@ impl<'a> Processor<'_> {
         // for each media file, copy it to it's desired ending location
         let mut col_rectifier = 0;
         let total_files = self.media_files.len();
+        let mut to_copy = Vec::with_capacity(total_files);
+        let mut planned_destinations = HashSet::with_capacity(total_files);
 
         for (i, file) in self.media_files.iter_mut().enumerate() {
-            println!("Copying file [{}/{}]: {}", i, total_files, file.media_path.display());
+            println!(
+                "Copying file [{}/{}]: {}",
+                i,
+                total_files,
+                file.media_path.display()
+            );
 
             let mut destination_path = file.destination_path.as_ref().unwrap();
             let media_path = &file.media_path;
@@ -845,44 +1825,267 @@ impl<'a> Processor<'_> {
                 }
             }
 
-            // check if the file already exists
-            if destination_path.exists() {
-                // if it does, then we need to change the filename
-                let mut new_dest_path = destination_path.clone();
-                new_dest_path.set_file_name(format!(
-                    "{}_{}.{}",
-                    new_dest_path.file_stem().unwrap().to_str().unwrap(),
-                    col_rectifier,
-                    new_dest_path.extension().unwrap().to_str().unwrap()
-                ));
-                col_rectifier += 1;
-
-                file.destination_path = Some(new_dest_path);
+            // check if the file already exists, or another file is already going to be copied there
+            let free_path =
+                free_destination(destination_path, &planned_destinations, &mut col_rectifier);
+            if free_path != *destination_path {
+                file.destination_path = Some(free_path);
                 destination_path = file.destination_path.as_ref().unwrap();
             }
 
-            // copy the file
-            std::fs::copy(media_path, destination_path)?;
+            planned_destinations.insert(destination_path.clone());
+            to_copy.push((media_path.clone(), destination_path.clone()));
+
+            // the other albums the file is in get theirs once exif data has been applied to this one
+            file.album_copies.clear();
+            if self.album_output != AlbumOutput::Manifest {
+                let own_album = file
+                    .destination_type
+                    .as_ref()
+                    .and_then(DestLocation::membership);
+                for album in file
+                    .albums
+                    .iter()
+                    .filter(|a| Some(*a) != own_album.as_ref())
+                {
+                    let copy_path = PathBuf::from(self.output_directory)
+                        .join(album.folder())
+                        .join(destination_path.file_name().unwrap());
+                    let copy_path =
+                        free_destination(&copy_path, &planned_destinations, &mut col_rectifier);
+                    planned_destinations.insert(copy_path.clone());
+                    file.album_copies.push(copy_path);
+                }
+            }
+        }
+
+        // copy the files, streaming each .tgz archive at most once
+        self.takeout.copy_all(&to_copy)?;
+
+        Ok(())
+    }
+
+    /// Put each file in the other albums it is in, once exif data has been applied to it, by copying or hardlinking
+    /// it into their folders depending on the album output. Then list every album in `albums.json`.
+    pub fn write_albums(&self) -> Result<(), Box<dyn std::error::Error>> {
+        if self.album_output == AlbumOutput::Manifest {
+            return self.write_album_manifest();
+        }
+
+        let mut written = 0;
+        for file in self.media_files.iter() {
+            let destination_path = file.destination_path.as_ref().unwrap();
+            for copy_path in file.album_copies.iter() {
+                if let Some(parent) = copy_path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                match self.album_output {
+                    AlbumOutput::Hardlink => std::fs::hard_link(destination_path, copy_path)?,
+                    _ => {
+                        std::fs::copy(destination_path, copy_path)?;
+                        // a copy gets new file times, so give it the ones the date was written into
+                        let metadata = std::fs::metadata(destination_path)?;
+                        let file_time = filetime::FileTime::from_last_modification_time(&metadata);
+                        filetime::set_file_times(copy_path, file_time, file_time)?;
+                    }
+                }
+                written += 1;
+            }
         }
 
+        println!("Put {} files into their other albums", written);
+        self.write_album_manifest()
+    }
+
+    /// Write `albums.json` to the output directory, with the title, description, date, cover and files of every
+    /// album, so an uploader can recreate them exactly rather than going by folder names.
+    pub fn write_album_manifest(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let manifest = self.album_manifest();
+        let manifest_path = Path::new(self.output_directory).join("albums.json");
+        std::fs::write(&manifest_path, serde_json::to_string_pretty(&manifest)?)?;
+        println!(
+            "Wrote {} albums to {}",
+            manifest.len(),
+            manifest_path.display()
+        );
         Ok(())
     }
 
+    /// a path in the output directory, relative to it
+    fn output_relative(&self, path: &Path) -> PathBuf {
+        path.strip_prefix(self.output_directory)
+            .unwrap_or(path)
+            .to_path_buf()
+    }
+
+    /// every album by its output folder name, as it is written to `albums.json`
+    fn album_manifest(&self) -> BTreeMap<&str, ManifestAlbum<'_>> {
+        let mut members: BTreeMap<&AlbumMembership, (Vec<&MediaFile>, Vec<&str>)> = BTreeMap::new();
+        for file in self.media_files.iter() {
+            for album in file.albums.iter() {
+                members.entry(album).or_default().0.push(file);
+            }
+        }
+        for skipped in self.skipped.iter() {
+            if let SkipReason::InImmich {
+                asset_id: Some(asset_id),
+                album: Some(album),
+            } = &skipped.reason
+            {
+                let in_immich = &mut members.entry(album).or_default().1;
+                if !in_immich.contains(&asset_id.as_str()) {
+                    in_immich.push(asset_id);
+                }
+            }
+        }
+
+        let mut manifest = BTreeMap::new();
+        for (album, (mut files, in_immich)) in members {
+            // undated files go last
+            files.sort_by(|a, b| {
+                (
+                    a.media_creation_date.is_none(),
+                    a.media_creation_date,
+                    &a.media_path,
+                )
+                    .cmp(&(
+                        b.media_creation_date.is_none(),
+                        b.media_creation_date,
+                        &b.media_path,
+                    ))
+            });
+            let files: Vec<PathBuf> = files
+                .iter()
+                .map(|file| self.output_relative(file.destination_path.as_ref().unwrap()))
+                .collect();
+            let is_video = |path: &&PathBuf| {
+                path.extension()
+                    .and_then(|ext| ext.to_str())
+                    .is_some_and(|ext| VIDEO_TYPES.contains(&ext.to_lowercase().as_str()))
+            };
+            let cover = files
+                .iter()
+                .find(|path| !is_video(path))
+                .or(files.first())
+                .cloned();
+
+            let metadata = self
+                .album_sources
+                .get(&album.album)
+                .and_then(|folder| self.albums.get(folder));
+            manifest.insert(
+                album.album.as_str(),
+                ManifestAlbum {
+                    title: metadata
+                        .map(|metadata| metadata.title.as_str())
+                        .filter(|title| !title.trim().is_empty())
+                        .unwrap_or(&album.album),
+                    description: metadata.map_or("", |metadata| metadata.description.as_str()),
+                    date: metadata.and_then(Album::date),
+                    location: metadata
+                        .and_then(|metadata| metadata.location.as_deref())
+                        .filter(|location| !location.trim().is_empty()),
+                    shared: album.shared,
+                    folder: album.folder(),
+                    cover,
+                    files,
+                    in_immich,
+                },
+            );
+        }
+        manifest
+    }
+
+    /// Upload the output to an Immich server, mark the files that were favourites or archived in Google Photos,
+    /// and recreate every album on it. Progress is saved to `immich-upload.json` in the output directory, so
+    /// running this again carries on where it stopped.
+    pub async fn upload_to_immich(
+        &self,
+        server: &str,
+        api_key: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let uploads: Vec<Upload> = self
+            .media_files
+            .iter()
+            .map(|file| {
+                let path = file.destination_path.clone().unwrap();
+                let sidecar = file
+                    .json_path
+                    .as_ref()
+                    .and_then(|json_path| self.sidecars.get(json_path));
+                Upload {
+                    key: self.output_relative(&path),
+                    path,
+                    taken_at: file
+                        .media_creation_date
+                        .map(|date| date.with_timezone(&Utc)),
+                    favorite: sidecar.is_some_and(|sidecar| sidecar.favorited),
+                    archived: sidecar.is_some_and(|sidecar| sidecar.archived),
+                }
+            })
+            .collect();
+        let albums: Vec<UploadAlbum> = self
+            .album_manifest()
+            .into_iter()
+            .map(|(name, album)| UploadAlbum {
+                name: name.to_owned(),
+                title: album.title.to_owned(),
+                description: album.description.to_owned(),
+                shared: album.shared,
+                files: album.files,
+                existing: album.in_immich.into_iter().map(str::to_owned).collect(),
+            })
+            .collect();
+
+        let state_path = Path::new(self.output_directory).join("immich-upload.json");
+        immich::upload(
+            &immich::Client::new(server, api_key),
+            &state_path,
+            &uploads,
+            &albums,
+        )
+        .await
+    }
+
     pub async fn apply_exif(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        // use exiftool to apply the date to the file using JSON where possible, otherwise use the one set in the file
-        // or ask for manual intervention
+        // use exiftool to apply the date to the file using JSON where possible, otherwise use the one found in the
+        // file's metadata, name or folder, or ask for manual intervention
 
         let counter = Arc::new(AtomicUsize::new(1));
         let total_media_files = self.media_files.len();
-        for chunk in self.media_files.chunks(1024) {
+        let takeout = &self.takeout;
+        let albums = &self.albums;
+        let exif_precision = self.exif_precision;
+        for (chunk_index, chunk) in self.media_files.chunks(1024).enumerate() {
             let mut futures = Vec::with_capacity(1024);
 
-            for media_file in chunk.iter() {
+            for (i, media_file) in chunk.iter().enumerate() {
                 let counter = counter.clone();
                 futures.push(async move {
 
                 // if JSON
-                if media_file.json_path.is_some() {
+                if let Some(json_path) = &media_file.json_path {
+                    let json_file = match takeout.read_to_string(json_path) {
+                        Ok(json_file) => json_file,
+                        Err(e) => {
+                            println!("[{}/{}] Applying exif to {}... FAILURE! `{}`", counter.fetch_add(1, Ordering::Relaxed), total_media_files, media_file.destination_path.as_ref().unwrap().display(), e);
+                            return;
+                        }
+                    };
+
+                    // exiftool can only read tags from a real file, so sidecars inside an archive are written out to a temporary file
+                    let (tags_path, is_temporary) = match takeout.real_path(json_path) {
+                        Some(real_path) => (real_path.to_path_buf(), false),
+                        None => {
+                            let temp_path = std::env::temp_dir().join(format!("gdog-{}-{}.json", std::process::id(), chunk_index * 1024 + i));
+                            if let Err(e) = tokio::fs::write(&temp_path, &json_file).await {
+                                println!("[{}/{}] Applying exif to {}... FAILURE! `{}`", counter.fetch_add(1, Ordering::Relaxed), total_media_files, media_file.destination_path.as_ref().unwrap().display(), e);
+                                return;
+                            }
+                            (temp_path, true)
+                        }
+                    };
+
                     // COPIED FROM: https://github.com/kaytat/exiftool-scripts-for-takeout
                     let process = tokio::process::Command::new("exiftool")
                         .args([
@@ -891,7 +2094,7 @@ impl<'a> Processor<'_> {
                             "($Filetype eq \"MP4\" and not $quicktime:TrackCreateDate) or ($Filetype eq \"MP4\" and $quicktime:TrackCreateDate eq \"0000:00:00 00:00:00\") or ($Filetype eq \"JPEG\" and not $exif:DateTimeOriginal) or ($Filetype eq \"PNG\" and not $PNG:CreationTime)",
 
                             "-tagsfromfile",
-                            format!("{}", media_file.json_path.as_ref().unwrap().display()).as_str(),
+                            format!("{}", tags_path.display()).as_str(),
 
                             // exif for regular jpg
                             "-AllDates<${PhotoTakenTimeTimestamp;$_=ConvertUnixTime($_,1)}",
@@ -915,6 +2118,10 @@ impl<'a> Processor<'_> {
                         .stderr(Stdio::piped())
                         .output().await;
 
+                    if is_temporary {
+                        let _ = tokio::fs::remove_file(&tags_path).await;
+                    }
+
                     if let Err(e) = process {
                         println!("[{}/{}] Applying exif to {}... FAILURE! `{}`", counter.fetch_add(1, Ordering::Relaxed), total_media_files, media_file.destination_path.as_ref().unwrap().display(), e);
                         return;
@@ -928,9 +2135,6 @@ impl<'a> Processor<'_> {
 
                     }
 
-                    // read the json file
-                    let json_file = tokio::fs::read_to_string(media_file.json_path.as_ref().unwrap()).await.unwrap();
-
                     // parse the json file
                     let json: serde_json::Value = serde_json::from_str(&json_file).unwrap();
 
@@ -941,9 +2145,9 @@ impl<'a> Processor<'_> {
 
                     // convert the epoch timestamps to DateTime
                     let crt_epoch = crt_timestamp.parse::<i64>().unwrap();
-                    let crt_epoch = chrono::NaiveDateTime::from_timestamp_opt(crt_epoch, 0).unwrap();
+                    let crt_epoch = chrono::DateTime::from_timestamp(crt_epoch, 0).unwrap();
                     let photo_epoch = photo_timestamp.parse::<i64>().unwrap();
-                    let photo_epoch = chrono::NaiveDateTime::from_timestamp_opt(photo_epoch, 0).unwrap();
+                    let photo_epoch = chrono::DateTime::from_timestamp(photo_epoch, 0).unwrap();
 
 
                     // select the earliest timestamp
@@ -961,6 +2165,43 @@ impl<'a> Processor<'_> {
                         let file_time = filetime::FileTime::from_unix_time(to_apply.timestamp(), to_apply.timestamp_subsec_nanos());
                         filetime::set_file_times(dest_path, file_time, file_time).unwrap();
                     }).await.unwrap();
+                } else if let Some(date) = media_file.media_creation_date {
+                    let dest_path = media_file.destination_path.clone().unwrap();
+
+                    // a date read from the file's own metadata is already in its tags
+                    if matches!(media_file.match_source, MatchSource::Embedded { .. }) {
+                        println!("[{}/{}] Keeping embedded date of {}", counter.fetch_add(1, Ordering::Relaxed), total_media_files, dest_path.display());
+                    } else {
+                        let precision = media_file.match_source.precision();
+                        let process = tokio::process::Command::new("exiftool")
+                            .args(inferred_date_args(&date, precision, exif_precision))
+                            .arg(&dest_path)
+                            .stdout(Stdio::piped())
+                            .stderr(Stdio::piped())
+                            .output().await;
+                        println!("[{}/{}] Applying {:?} date to {}... {}", counter.fetch_add(1, Ordering::Relaxed), total_media_files, precision, dest_path.display(), exiftool_outcome(process));
+                    }
+
+                    // a file dated by its album was most likely taken where the album was too
+                    let album_location = match media_file.match_source {
+                        MatchSource::AlbumDate => albums.get(media_file.media_path.parent().unwrap()).and_then(Album::coordinates),
+                        _ => None,
+                    };
+                    if let Some((latitude, longitude)) = album_location {
+                        let process = tokio::process::Command::new("exiftool")
+                            .args(album_location_args(latitude, longitude))
+                            .arg(&dest_path)
+                            .stdout(Stdio::piped())
+                            .stderr(Stdio::piped())
+                            .output().await;
+                        println!("Applying album location to {}... {}", dest_path.display(), exiftool_outcome(process));
+                    }
+
+                    // file times always hold a full time, so they get the start of the range a partial date stands for
+                    tokio::task::spawn_blocking(move || {
+                        let file_time = filetime::FileTime::from_unix_time(date.timestamp(), date.timestamp_subsec_nanos());
+                        filetime::set_file_times(dest_path, file_time, file_time).unwrap();
+                    }).await.unwrap();
                 } else {
                     println!("NO JSON FOUND!");
                     // print the media file and all information with it
@@ -978,72 +2219,1097 @@ impl<'a> Processor<'_> {
 
 #[cfg(test)]
 mod tests {
-    use std::path::PathBuf;
+    use std::{
+        io::{Cursor, Write},
+        path::{Path, PathBuf},
+    };
+
+    use zip::{write::FileOptions, ZipWriter};
+
+    use chrono::{DateTime, NaiveDate};
+
+    use crate::{
+        album::Album,
+        album_location_args, inferred_date_args,
+        review::{Decision, Decisions},
+        sidecar::SidecarIndex,
+        AlbumMembership, AlbumOutput, DatePrecision, FuzzyCandidate, MatchSource, MediaFile,
+        Processor, SkipReason,
+    };
+
+    fn write_takeout_part(name: &str, files: &[(&str, &[u8])]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("{}-{}.zip", name, std::process::id()));
+        let mut writer = ZipWriter::new(std::fs::File::create(&path).unwrap());
+        for (name, contents) in files {
+            writer.start_file(*name, FileOptions::default()).unwrap();
+            writer.write_all(contents).unwrap();
+        }
+        writer.finish().unwrap();
+        path
+    }
 
-    use crate::json_path_from_media_path;
+    /// resolve the sidecar of `media_path` out of a directory holding the given json files
+    fn resolve_json_path(media_path: &str, json_paths: &[&str]) -> Option<PathBuf> {
+        let mut index = SidecarIndex::default();
+        for json_path in json_paths {
+            index.insert_name(Path::new(json_path));
+        }
+        index.resolve(Path::new(media_path)).cloned()
+    }
 
     #[test]
     fn test_json_path_brackets_at_end() {
-        let media_path = PathBuf::from("/home/tester/images/my_bracket(1).png");
-        let json_path = json_path_from_media_path(&media_path).remove(0);
+        let json_path = resolve_json_path(
+            "/home/tester/images/my_bracket(1).png",
+            &[
+                "/home/tester/images/my_bracket(1).png.json",
+                "/home/tester/images/my_bracket.png(1).json",
+            ],
+        );
         assert_eq!(
             json_path,
-            PathBuf::from("/home/tester/images/my_bracket.png(1).json")
+            Some(PathBuf::from("/home/tester/images/my_bracket.png(1).json"))
         );
     }
 
     #[test]
     fn test_json_path_brackets_invalid_at_end() {
-        let media_path = PathBuf::from("/home/tester/images/my_bracket(ooga).png");
-        let json_path = json_path_from_media_path(&media_path).remove(0);
+        let json_path = resolve_json_path(
+            "/home/tester/images/my_bracket(ooga).png",
+            &[
+                "/home/tester/images/my_bracket.png.json",
+                "/home/tester/images/my_bracket(ooga).png.json",
+            ],
+        );
         assert_eq!(
             json_path,
-            PathBuf::from("/home/tester/images/my_bracket(ooga).png.json")
+            Some(PathBuf::from(
+                "/home/tester/images/my_bracket(ooga).png.json"
+            ))
         );
     }
 
     #[test]
     fn test_heic_file_with_video() {
-        let media_path = PathBuf::from(
+        let json_path = resolve_json_path(
             "/home/tester/images/66694115136__8679EE1A-E4B4-4D1B-B76C-510A6E58C.HEIC",
+            &[
+                "/home/tester/images/66694115136__8679EE1A-E4B4-4D1B-B76C-510A6E58C.HEIC.json",
+                "/home/tester/images/66694115136__8679EE1A-E4B4-4D1B-B76C-510A6E58C.json",
+            ],
         );
-        let json_path = json_path_from_media_path(&media_path).remove(0);
         assert_eq!(
             json_path,
-            PathBuf::from(
+            Some(PathBuf::from(
                 "/home/tester/images/66694115136__8679EE1A-E4B4-4D1B-B76C-510A6E58C.json"
-            )
+            ))
         );
     }
 
     #[test]
     fn test_heic_file_rough() {
-        let media_path =
-            PathBuf::from("/home/tester/images/Google Photos/Photos from 2020/IMG_2433.HEIC");
-        let json_path = json_path_from_media_path(&media_path).remove(1);
+        let json_path = resolve_json_path(
+            "/home/tester/images/Google Photos/Photos from 2020/IMG_2433.HEIC",
+            &["/home/tester/images/Google Photos/Photos from 2020/IMG_2433.HEIC.json"],
+        );
         assert_eq!(
             json_path,
-            PathBuf::from("/home/tester/images/Google Photos/Photos from 2020/IMG_2433.HEIC.json")
+            Some(PathBuf::from(
+                "/home/tester/images/Google Photos/Photos from 2020/IMG_2433.HEIC.json"
+            ))
         );
     }
 
     #[test]
     fn test_json_path_brackets_at_end_double_digit() {
-        let media_path = PathBuf::from("/home/tester/images/my_bracket(16).png");
-        let json_path = json_path_from_media_path(&media_path).remove(0);
+        let json_path = resolve_json_path(
+            "/home/tester/images/my_bracket(16).png",
+            &[
+                "/home/tester/images/my_bracket(1).png.json",
+                "/home/tester/images/my_bracket.png(1).json",
+                "/home/tester/images/my_bracket.png(16).json",
+            ],
+        );
         assert_eq!(
             json_path,
-            PathBuf::from("/home/tester/images/my_bracket.png(16).json")
+            Some(PathBuf::from("/home/tester/images/my_bracket.png(16).json"))
         );
     }
 
     #[test]
     fn test_bracket_ordering() {
-        let media_path = PathBuf::from("/home/josiah/Documents/g-takeout-processor/gdog/takeout/Google Photos/Photos from 2018/2018-06-17 01_54_22-13th June - OneNote 2016(1).png");
-        let json_path = json_path_from_media_path(&media_path).remove(0);
+        let json_path = resolve_json_path(
+            "/home/josiah/Documents/g-takeout-processor/gdog/takeout/Google Photos/Photos from 2018/2018-06-17 01_54_22-13th June - OneNote 2016(1).png",
+            &[
+                "/home/josiah/Documents/g-takeout-processor/gdog/takeout/Google Photos/Photos from 2018/2018-06-17 01_54_22-13th June - OneNote 2016.png.json",
+                "/home/josiah/Documents/g-takeout-processor/gdog/takeout/Google Photos/Photos from 2018/2018-06-17 01_54_22-13th June - OneNote 2016.png(1).json",
+            ],
+        );
+        assert_eq!(
+            json_path,
+            Some(PathBuf::from("/home/josiah/Documents/g-takeout-processor/gdog/takeout/Google Photos/Photos from 2018/2018-06-17 01_54_22-13th June - OneNote 2016.png(1).json"))
+        );
+    }
+
+    #[test]
+    fn test_json_path_jpg_cut_short() {
+        let json_path = resolve_json_path(
+            "/home/tester/images/Holiday photos from our trip to Queenstown (2).jpg",
+            &["/home/tester/images/Holiday photos from our trip to Queenstown 2.j(2).json"],
+        );
+        assert_eq!(
+            json_path,
+            Some(PathBuf::from(
+                "/home/tester/images/Holiday photos from our trip to Queenstown 2.j(2).json"
+            ))
+        );
+    }
+
+    #[test]
+    fn test_sidecar_in_another_part() {
+        let first_part = write_takeout_part(
+            "takeout-20240101T000000Z-001",
+            &[("Takeout/Google Photos/Trip/IMG_0001.jpg", b"fake jpeg")],
+        );
+        let second_part = write_takeout_part(
+            "takeout-20240101T000000Z-002",
+            &[(
+                "Takeout/Google Photos/Trip/IMG_0001.jpg.json",
+                br#"{"title": "IMG_0001.jpg"}"#,
+            )],
+        );
+
+        let first_part_str = first_part.to_str().unwrap();
+        let mut processor = Processor::new(first_part_str, "/tmp/unused");
+        processor.add_input(&second_part);
+        processor.load_files().unwrap();
+
+        assert_eq!(processor.media_files.len(), 1);
+        assert_eq!(
+            processor.media_files[0].json_path,
+            Some(PathBuf::from(
+                "Takeout/Google Photos/Trip/IMG_0001.jpg.json"
+            ))
+        );
+        assert!(processor.json_files.is_empty());
+
+        std::fs::remove_file(first_part).unwrap();
+        std::fs::remove_file(second_part).unwrap();
+    }
+
+    #[test]
+    fn test_json_path_supplemental_metadata() {
+        for expected in [
+            "/home/tester/images/IMG_1234.JPG.supplemental-metadata.json",
+            "/home/tester/images/IMG_1234.JPG.supplemental-metad.json",
+            "/home/tester/images/IMG_1234.JPG.suppl.json",
+        ] {
+            let json_path = resolve_json_path("/home/tester/images/IMG_1234.JPG", &[expected]);
+            assert_eq!(json_path, Some(PathBuf::from(expected)));
+        }
+    }
+
+    #[test]
+    fn test_json_path_supplemental_metadata_brackets() {
+        for expected in [
+            "/home/tester/images/IMG_1234.JPG.supplemental-metadata(1).json",
+            "/home/tester/images/IMG_1234.JPG.supplemental-met(1).json",
+        ] {
+            let json_path = resolve_json_path(
+                "/home/tester/images/IMG_1234(1).JPG",
+                &[
+                    "/home/tester/images/IMG_1234.JPG.supplemental-metadata.json",
+                    expected,
+                ],
+            );
+            assert_eq!(json_path, Some(PathBuf::from(expected)));
+        }
+    }
+
+    #[test]
+    fn test_json_path_supplemental_metadata_edited() {
+        let json_path = resolve_json_path(
+            "/home/tester/images/IMG_1234-edited.jpg",
+            &["/home/tester/images/IMG_1234.jpg.supplemental-metadata.json"],
+        );
+        assert_eq!(
+            json_path,
+            Some(PathBuf::from(
+                "/home/tester/images/IMG_1234.jpg.supplemental-metadata.json"
+            ))
+        );
+    }
+
+    #[test]
+    fn test_json_path_supplemental_before_truncation_guesses() {
+        // `IMG_1.json` belongs to a different photo, so it must only be used after the real sidecar names
+        let json_path = resolve_json_path(
+            "/home/tester/images/IMG_12.jpg",
+            &[
+                "/home/tester/images/IMG_1.json",
+                "/home/tester/images/IMG_12.jpg.supplemental-metadata.json",
+            ],
+        );
+        assert_eq!(
+            json_path,
+            Some(PathBuf::from(
+                "/home/tester/images/IMG_12.jpg.supplemental-metadata.json"
+            ))
+        );
+    }
+
+    #[test]
+    fn test_folder_dates_keep_their_range() {
+        let mut processor = Processor::new("/tmp/unused", "/tmp/unused");
+        processor.media_files.push(MediaFile {
+            media_path: PathBuf::from("Takeout/Google Photos/Summer 2016/IMG_0001.jpg"),
+            destination_path: None,
+            destination_type: None,
+            json_path: None,
+            media_creation_date: None,
+            match_source: MatchSource::NoMatch,
+            fuzzy_candidates: Vec::new(),
+            fuzzy_conflicts: Vec::new(),
+            albums: Vec::new(),
+            album_copies: Vec::new(),
+        });
+
+        processor.find_date_time_from_directory();
+
+        let file = &processor.media_files[0];
+        assert_eq!(
+            file.media_creation_date.unwrap().date_naive(),
+            NaiveDate::from_ymd_opt(2016, 6, 1).unwrap()
+        );
+        match &file.match_source {
+            MatchSource::DirectoryName {
+                folder,
+                precision,
+                end,
+            } => {
+                assert_eq!(folder, "Summer 2016");
+                assert_eq!(*precision, DatePrecision::Year);
+                assert_eq!(*end, NaiveDate::from_ymd_opt(2016, 8, 31).unwrap());
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_fuzzy_matches_are_one_to_one() {
+        let mut processor = Processor::new("/tmp/unused", "/tmp/unused");
+        for name in ["Trip/IMG_0001 copy.jpg", "Trip/IMG_0001 copy 2.jpg"] {
+            processor.media_files.push(MediaFile {
+                media_path: PathBuf::from(name),
+                destination_path: None,
+                destination_type: None,
+                json_path: None,
+                media_creation_date: None,
+                match_source: MatchSource::NoMatch,
+                fuzzy_candidates: Vec::new(),
+                fuzzy_conflicts: Vec::new(),
+                albums: Vec::new(),
+                album_copies: Vec::new(),
+            });
+        }
+        processor
+            .json_files
+            .insert(PathBuf::from("Trip/IMG_0001 copy.jpg.json"));
+
+        processor.fuzzy_match_filenames().unwrap();
+
+        let (winner, loser) = (&processor.media_files[0], &processor.media_files[1]);
+        assert_eq!(
+            winner.json_path,
+            Some(PathBuf::from("Trip/IMG_0001 copy.jpg.json"))
+        );
+        assert_eq!(loser.json_path, None);
+        assert!(matches!(loser.match_source, MatchSource::NoMatch));
+        assert_eq!(loser.fuzzy_conflicts.len(), 1);
+        assert_eq!(loser.fuzzy_conflicts[0].taken_by, winner.media_path);
+        assert!(!loser.fuzzy_candidates.is_empty());
+        assert!(processor.json_files.is_empty());
+
+        // the file that lost out is still put in front of the reviewer
+        let review_file =
+            std::env::temp_dir().join(format!("gdog-review-loser-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&review_file);
+        processor.set_review_file(&review_file).unwrap();
+        let mut output = Vec::new();
+        processor
+            .review_fuzzy_matches(&mut Cursor::new("\n\n"), &mut output)
+            .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("[2/2] Fuzzy match for Trip/IMG_0001 copy 2.jpg"));
+        assert!(output.contains("No match was made"));
+        assert_eq!(
+            processor.media_files[0].json_path,
+            Some(PathBuf::from("Trip/IMG_0001 copy.jpg.json"))
+        );
+        assert!(matches!(
+            processor.media_files[1].match_source,
+            MatchSource::NoMatch
+        ));
+
+        std::fs::remove_file(review_file).unwrap();
+    }
+
+    #[test]
+    fn test_review_keeps_the_fuzzy_assignment() {
+        let review_file =
+            std::env::temp_dir().join(format!("gdog-review-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&review_file);
+        let candidate = |json_path: &str, score| FuzzyCandidate {
+            json_path: PathBuf::from(json_path),
+            score,
+        };
+
+        let mut processor = Processor::new("/tmp/unused", "/tmp/unused");
+        // the second file lost its best sidecar to the first one, so it was matched to its runner-up
+        for (name, json_path, score, candidates) in [
+            (
+                "Trip/IMG_0001 copy.jpg",
+                "Trip/IMG_0001 copy.jpg.json",
+                97,
+                vec![candidate("Trip/IMG_0001 copy.jpg.json", 97)],
+            ),
+            (
+                "Trip/IMG_0001 copy 2.jpg",
+                "Trip/IMG_0001 copy 2.json",
+                91,
+                vec![
+                    candidate("Trip/IMG_0001 copy.jpg.json", 95),
+                    candidate("Trip/IMG_0001 copy 2.json", 91),
+                ],
+            ),
+        ] {
+            processor.media_files.push(MediaFile {
+                media_path: PathBuf::from(name),
+                destination_path: None,
+                destination_type: None,
+                json_path: Some(PathBuf::from(json_path)),
+                media_creation_date: None,
+                match_source: MatchSource::FuzzyMatch { score },
+                fuzzy_candidates: candidates,
+                fuzzy_conflicts: Vec::new(),
+                albums: Vec::new(),
+                album_copies: Vec::new(),
+            });
+        }
+        let original = processor.media_files.clone();
+
+        // pressing enter for both keeps things as they were
+        processor.set_review_file(&review_file).unwrap();
+        processor
+            .review_fuzzy_matches(&mut Cursor::new("\n\n"), &mut Vec::new())
+            .unwrap();
+        let json_paths: Vec<_> = processor
+            .media_files
+            .iter()
+            .map(|file| file.json_path.clone().unwrap())
+            .collect();
+        assert_eq!(
+            json_paths,
+            [
+                PathBuf::from("Trip/IMG_0001 copy.jpg.json"),
+                PathBuf::from("Trip/IMG_0001 copy 2.json")
+            ]
+        );
+
+        // giving the second file the first one's sidecar asks about the first file again
+        std::fs::remove_file(&review_file).unwrap();
+        processor.media_files = original;
+        processor.set_review_file(&review_file).unwrap();
+        let mut output = Vec::new();
+        processor
+            .review_fuzzy_matches(&mut Cursor::new("\n1\n\n"), &mut output)
+            .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("Trip/IMG_0001 copy.jpg lost Trip/IMG_0001 copy.jpg.json"));
+        assert!(output.contains("[3/3] Fuzzy match for Trip/IMG_0001 copy.jpg"));
+
+        let (loser, winner) = (&processor.media_files[0], &processor.media_files[1]);
+        assert_eq!(
+            winner.json_path,
+            Some(PathBuf::from("Trip/IMG_0001 copy.jpg.json"))
+        );
+        assert_eq!(loser.json_path, None);
+        assert!(matches!(loser.match_source, MatchSource::NoMatch));
+        assert_eq!(loser.fuzzy_conflicts[0].taken_by, winner.media_path);
+        assert!(processor
+            .json_files
+            .contains(Path::new("Trip/IMG_0001 copy 2.json")));
+
+        let decisions = Decisions::load(&review_file).unwrap();
+        assert_eq!(decisions.get(&loser.media_path), Some(&Decision::Rejected));
+
+        std::fs::remove_file(review_file).unwrap();
+    }
+
+    #[test]
+    fn test_review_leaves_sidecars_matched_by_name() {
+        let part = write_takeout_part(
+            "takeout-review-held",
+            &[
+                ("Takeout/Google Photos/Trip/IMG_0001.jpg.json", b"{}"),
+                ("Takeout/Google Photos/Trip/IMG_0001 copy.json", b"{}"),
+            ],
+        );
+        let review_file =
+            std::env::temp_dir().join(format!("gdog-review-held-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&review_file);
+        let (held, other) = (
+            PathBuf::from("Takeout/Google Photos/Trip/IMG_0001.jpg.json"),
+            PathBuf::from("Takeout/Google Photos/Trip/IMG_0001 copy.json"),
+        );
+
+        let mut processor = Processor::new("/tmp/unused", "/tmp/unused");
+        processor.takeout.add_zip(&part).unwrap();
+        // a newer takeout brought the file the sidecar was written for, after it was picked for the copy
+        for (name, json_path, match_source) in [
+            (
+                "Takeout/Google Photos/Trip/IMG_0001.jpg",
+                &held,
+                MatchSource::JsonFile,
+            ),
+            (
+                "Takeout/Google Photos/Trip/IMG_0001 copy.jpg",
+                &other,
+                MatchSource::FuzzyMatch { score: 90 },
+            ),
+        ] {
+            processor.media_files.push(MediaFile {
+                media_path: PathBuf::from(name),
+                destination_path: None,
+                destination_type: None,
+                json_path: Some(json_path.clone()),
+                media_creation_date: None,
+                match_source,
+                fuzzy_candidates: vec![
+                    FuzzyCandidate {
+                        json_path: held.clone(),
+                        score: 95,
+                    },
+                    FuzzyCandidate {
+                        json_path: other.clone(),
+                        score: 90,
+                    },
+                ],
+                fuzzy_conflicts: Vec::new(),
+                albums: Vec::new(),
+                album_copies: Vec::new(),
+            });
+        }
+        let copy = processor.media_files[1].media_path.clone();
+        Decisions::load(&review_file)
+            .unwrap()
+            .record(
+                copy.clone(),
+                Decision::Accepted {
+                    json_path: held.clone(),
+                },
+            )
+            .unwrap();
+
+        // the saved answer is dropped, and picking the same sidecar again is refused
+        processor.set_review_file(&review_file).unwrap();
+        let mut output = Vec::new();
+        processor
+            .review_fuzzy_matches(&mut Cursor::new("1\n2\n"), &mut output)
+            .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(
+            output.contains("[1/1] Fuzzy match for Takeout/Google Photos/Trip/IMG_0001 copy.jpg")
+        );
+        assert!(output.contains("Trip/IMG_0001.jpg.json is already the sidecar of Takeout/Google Photos/Trip/IMG_0001.jpg"));
+        assert_eq!(processor.media_files[0].json_path, Some(held));
+        assert_eq!(processor.media_files[1].json_path, Some(other.clone()));
+
+        let decisions = Decisions::load(&review_file).unwrap();
+        assert_eq!(
+            decisions.get(&copy),
+            Some(&Decision::Accepted { json_path: other })
+        );
+
+        std::fs::remove_file(review_file).unwrap();
+        std::fs::remove_file(part).unwrap();
+    }
+
+    #[test]
+    fn test_sidecar_in_another_directory() {
+        let part = write_takeout_part(
+            "takeout-cross-directory",
+            &[
+                ("Takeout/Google Photos/Trip/IMG_0001.jpg", b"fake jpeg"),
+                (
+                    "Takeout/Google Photos/Photos from 2019/IMG_0001.jpg.json",
+                    br#"{"title": "IMG_0001.jpg"}"#,
+                ),
+                ("Takeout/Google Photos/Trip/PXL_20190704_120000.jpg", b"fake jpeg"),
+                (
+                    "Takeout/Google Photos/Photos from 2019/PXL_20190704_120000.jpg.json",
+                    br#"{"title": "PXL_20190704_120000.jpg", "photoTakenTime": {"timestamp": "1562241600"}}"#,
+                ),
+                (
+                    "Takeout/Google Photos/Photos from 2020/PXL_20190704_120000.jpg.json",
+                    br#"{"title": "PXL_20190704_120000.jpg", "photoTakenTime": {"timestamp": "1593864000"}}"#,
+                ),
+            ],
+        );
+
+        let mut processor = Processor::new(part.to_str().unwrap(), "/tmp/unused");
+        processor.load_files().unwrap();
+
+        let file = |name: &str| {
+            processor
+                .media_files
+                .iter()
+                .find(|file| file.media_path.ends_with(name))
+                .unwrap()
+        };
+        let plain = file("IMG_0001.jpg");
+        assert_eq!(
+            plain.json_path,
+            Some(PathBuf::from(
+                "Takeout/Google Photos/Photos from 2019/IMG_0001.jpg.json"
+            ))
+        );
+        assert!(matches!(
+            plain.match_source,
+            MatchSource::CrossDirectory { timestamp: false }
+        ));
+
+        let dated = file("PXL_20190704_120000.jpg");
+        assert_eq!(
+            dated.json_path,
+            Some(PathBuf::from(
+                "Takeout/Google Photos/Photos from 2019/PXL_20190704_120000.jpg.json"
+            ))
+        );
+        assert!(matches!(
+            dated.match_source,
+            MatchSource::CrossDirectory { timestamp: true }
+        ));
+
+        std::fs::remove_file(part).unwrap();
+    }
+
+    #[test]
+    fn test_inferred_dates_keep_their_precision() {
+        use chrono::{Local, TimeZone, Utc};
+
+        let date = Local.with_ymd_and_hms(2014, 6, 1, 0, 0, 0).unwrap();
+        let args = inferred_date_args(&date, DatePrecision::Year, DatePrecision::Day);
+        assert_eq!(
+            args,
+            ["-XMP-photoshop:DateCreated=2014", "-overwrite_original"]
+        );
+        let args = inferred_date_args(&date, DatePrecision::Month, DatePrecision::Day);
+        assert_eq!(args[0], "-XMP-photoshop:DateCreated=2014:06");
+        assert_eq!(args.len(), 2);
+
+        // precise enough to be stamped into the EXIF and QuickTime tags too, which hold the time in UTC
+        let date = Local.with_ymd_and_hms(2019, 1, 1, 12, 34, 56).unwrap();
+        let args = inferred_date_args(&date, DatePrecision::Time, DatePrecision::Day);
+        assert!(args.contains(&"-EXIF:DateTimeOriginal=2019:01:01 12:34:56".to_owned()));
+        let utc = date.with_timezone(&Utc).format("%Y:%m:%d %H:%M:%S");
+        assert!(args.contains(&format!("-QuickTime:CreateDate={}", utc)));
+
+        // unless the flag asks for more
+        let args = inferred_date_args(&date, DatePrecision::Day, DatePrecision::Time);
+        assert_eq!(
+            args,
+            [
+                "-XMP-photoshop:DateCreated=2019:01:01",
+                "-overwrite_original"
+            ]
+        );
+        assert_eq!("month".parse(), Ok(DatePrecision::Month));
+        assert!("week".parse::<DatePrecision>().is_err());
+    }
+
+    #[test]
+    fn test_album_date_is_the_last_resort() {
+        let part = write_takeout_part(
+            "takeout-album-metadata",
+            &[
+                (
+                    "Takeout/Google Photos/Mum_s 60th/metadata.json",
+                    r#"{"title": "Mum's 60th 🎉", "description": "At the bach", "date": {"timestamp": "1562241600"}, "geoData": {"latitude": -37.8, "longitude": 174.87}}"#.as_bytes(),
+                ),
+                ("Takeout/Google Photos/Mum_s 60th/Cake.jpg", b"fake jpeg"),
+                ("Takeout/Google Photos/Mum_s 60th/IMG-20190705-WA0001.jpg", b"fake jpeg"),
+            ],
+        );
+
+        let mut processor = Processor::new(part.to_str().unwrap(), "/tmp/unused");
+        processor.load_files().unwrap();
+
+        let file = |name: &str| {
+            processor
+                .media_files
+                .iter()
+                .find(|file| file.media_path.ends_with(name))
+                .unwrap()
+        };
+        let cake = file("Cake.jpg");
+        assert!(matches!(cake.match_source, MatchSource::AlbumDate));
+        assert_eq!(cake.media_creation_date.unwrap().timestamp(), 1_562_241_600);
+        let album = processor.album_of(&cake.media_path).unwrap();
+        assert_eq!(album.title, "Mum's 60th 🎉");
+        assert_eq!(album.description, "At the bach");
+        assert!(matches!(
+            file("IMG-20190705-WA0001.jpg").match_source,
+            MatchSource::FileName { .. }
+        ));
+
+        // only written if the file has no location of its own
+        let args = album_location_args(-37.8, 174.87);
+        assert_eq!(
+            args[..2],
+            ["-if", "not $GPSLatitude and not $GPSCoordinates"]
+        );
+        assert!(args.contains(&"-GPSLatitudeRef=S".to_owned()));
+
+        std::fs::remove_file(part).unwrap();
+    }
+
+    #[test]
+    fn test_album_folders_are_named_by_title() {
+        let metadata = |title: &str| format!(r#"{{"title": "{}"}}"#, title).into_bytes();
+        let part = write_takeout_part(
+            "takeout-album-titles",
+            &[
+                (
+                    "Takeout/Google Photos/Mum_s 60th/metadata.json",
+                    metadata("Mum's 60th 🎉"),
+                ),
+                (
+                    "Takeout/Google Photos/Mum_s 60th/Cake.jpg",
+                    b"fake jpeg".to_vec(),
+                ),
+                (
+                    "Takeout/Google Photos/Trip/metadata.json",
+                    metadata("Trip: Rome/Paris"),
+                ),
+                (
+                    "Takeout/Google Photos/Trip/Colosseum.jpg",
+                    b"fake jpeg".to_vec(),
+                ),
+                (
+                    "Takeout/Google Photos/Trip(1)/metadata.json",
+                    metadata("trip: rome/paris"),
+                ),
+                (
+                    "Takeout/Google Photos/Trip(1)/Louvre.jpg",
+                    b"fake jpeg".to_vec(),
+                ),
+                (
+                    "Takeout/Google Photos/Old scans/Slide.jpg",
+                    b"fake jpeg".to_vec(),
+                ),
+                (
+                    "Takeout/Google Photos/Photos from 2019/Beach.jpg",
+                    b"fake jpeg".to_vec(),
+                ),
+            ]
+            .iter()
+            .map(|(path, contents)| (*path, contents.as_slice()))
+            .collect::<Vec<_>>(),
+        );
+
+        let mut processor = Processor::new(part.to_str().unwrap(), "/tmp/unused");
+        processor.load_files().unwrap();
+
+        let names = processor.album_folder_names();
+        let name = |folder: &str| {
+            names[Path::new("Takeout/Google Photos").join(folder).as_path()].as_str()
+        };
+        assert_eq!(name("Mum_s 60th"), "Mum's 60th 🎉");
+        assert_eq!(name("Trip"), "Trip_ Rome_Paris");
+        // the same title differs only in case, which would collide on windows and macos
+        assert_eq!(name("Trip(1)"), "trip_ rome_paris (2)");
+        // no metadata, so the folder name is kept
+        assert_eq!(name("Old scans"), "Old scans");
+        assert_eq!(names.len(), 4);
+
+        std::fs::remove_file(part).unwrap();
+    }
+
+    #[test]
+    fn test_shared_albums_keep_their_titles() {
+        let part = write_takeout_part(
+            "takeout-shared-albums",
+            &[
+                (
+                    "Takeout/Google Photos/Untitled/metadata.json",
+                    br#"{"title": "Family dinners"}"#,
+                ),
+                ("Takeout/Google Photos/Untitled/Roast.jpg", b"fake jpeg"),
+                (
+                    "Takeout/Google Photos/Untitled(1)/metadata.json",
+                    br#"{"title": "Cousins"}"#,
+                ),
+                ("Takeout/Google Photos/Untitled(1)/Beach.jpg", b"fake jpeg"),
+                (
+                    "Takeout/Google Photos/Untitled(2)/metadata.json",
+                    br#"{"title": ""}"#,
+                ),
+                ("Takeout/Google Photos/Untitled(2)/Blurry.jpg", b"fake jpeg"),
+                (
+                    "Takeout/Google Photos/Cousins/metadata.json",
+                    br#"{"title": "Cousins"}"#,
+                ),
+                ("Takeout/Google Photos/Cousins/Picnic.jpg", b"fake jpeg"),
+            ],
+        );
+
+        let mut processor = Processor::new(part.to_str().unwrap(), "/tmp/unused");
+        processor.load_files().unwrap();
+
+        let names = processor.album_folder_names();
+        let name = |folder: &str| {
+            names[Path::new("Takeout/Google Photos").join(folder).as_path()].as_str()
+        };
+        assert_eq!(name("Untitled"), "Family dinners");
+        // owned and shared albums end up as albums of the same server, so their names are kept apart too
+        assert_eq!(name("Cousins"), "Cousins");
+        assert_eq!(name("Untitled(1)"), "Cousins (2)");
+        // nothing better to go on than the folder, which still keeps it apart from the other shared albums
+        assert_eq!(name("Untitled(2)"), "Untitled(2)");
+
+        std::fs::remove_file(part).unwrap();
+    }
+
+    #[test]
+    fn test_extensions_follow_file_contents() {
+        let part = write_takeout_part(
+            "takeout-extensions",
+            &[
+                (
+                    "Takeout/Google Photos/Trip/IMG_1.HEIC",
+                    b"\xff\xd8\xff\xe0 converted",
+                ),
+                (
+                    "Takeout/Google Photos/Trip/IMG_2.JPG",
+                    b"\xff\xd8\xff\xe1 original",
+                ),
+                (
+                    "Takeout/Google Photos/Trip/clip.mov",
+                    b"\x00\x00\x00\x14ftypisom\x00\x00\x00\x00isom",
+                ),
+                ("Takeout/Google Photos/Trip/notes.dat", b"no idea"),
+                ("Takeout/Google Photos/Trip/camcorder.MTS", &[0x47; 1024]),
+            ],
+        );
+
+        let mut processor = Processor::new(part.to_str().unwrap(), "/tmp/unused");
+        processor.load_files().unwrap();
+        processor.generate_destination_paths().unwrap();
+
+        let mut destinations: Vec<_> = processor
+            .media_files
+            .iter()
+            .map(|file| {
+                file.destination_path
+                    .as_ref()
+                    .unwrap()
+                    .file_name()
+                    .unwrap()
+                    .to_owned()
+            })
+            .collect();
+        destinations.sort();
+        assert_eq!(
+            destinations,
+            [
+                "IMG_1.jpg",
+                "IMG_2.JPG",
+                "camcorder.MTS",
+                "clip.mp4",
+                "notes.dat"
+            ]
+        );
+
+        std::fs::remove_file(part).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_photos_stay_in_every_album() {
+        // the contents aren't a format that can be detected, so the files keep their extensions
+        let part = write_takeout_part(
+            "takeout-many-albums",
+            &[
+                ("Takeout/Google Photos/Trip/clip.MTS", b"the same clip"),
+                ("Takeout/Google Photos/Beach/clip.MTS", b"the same clip"),
+                ("Takeout/Google Photos/Untitled/clip.MTS", b"the same clip"),
+                (
+                    "Takeout/Google Photos/Photos from 2019/clip.MTS",
+                    b"the same clip",
+                ),
+                ("Takeout/Google Photos/Trip/other.MTS", b"another clip"),
+            ],
+        );
+        let output = std::env::temp_dir().join(format!("gdog-many-albums-{}", std::process::id()));
+        std::fs::create_dir_all(&output).unwrap();
+        let output_directory = output.to_str().unwrap();
+
+        let mut processor = Processor::new(part.to_str().unwrap(), output_directory);
+        processor.set_album_output(AlbumOutput::Hardlink);
+        processor.load_files().unwrap();
+        processor.generate_destination_paths().unwrap();
+        processor.remove_duplicates().await.unwrap();
+
+        assert_eq!(processor.media_files.len(), 2);
+        let kept = processor
+            .media_files
+            .iter()
+            .find(|file| file.media_path.ends_with("clip.MTS"))
+            .unwrap();
+        assert!(kept.media_path.starts_with("Takeout/Google Photos/Beach"));
+        let albums: Vec<_> = kept
+            .albums
+            .iter()
+            .map(|album| (album.album.as_str(), album.shared))
+            .collect();
+        assert_eq!(
+            albums,
+            [("Beach", false), ("Trip", false), ("Untitled", true)]
+        );
+
+        processor.copy_files().unwrap();
+        processor.write_albums().unwrap();
+        for path in [
+            "albums/Beach/clip.MTS",
+            "albums/Trip/clip.MTS",
+            "shared/Untitled/clip.MTS",
+        ] {
+            assert_eq!(std::fs::read(output.join(path)).unwrap(), b"the same clip");
+        }
+        assert!(!output.join("general/clip.MTS").exists());
+
+        processor.set_album_output(AlbumOutput::Manifest);
+        processor.write_albums().unwrap();
+        let manifest: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(output.join("albums.json")).unwrap())
+                .unwrap();
+        assert_eq!(manifest["Trip"]["files"][0], "albums/Beach/clip.MTS");
+        assert_eq!(manifest["Trip"]["files"][1], "albums/Trip/other.MTS");
+        assert_eq!(manifest["Untitled"]["shared"], true);
+
+        std::fs::remove_dir_all(output).unwrap();
+        std::fs::remove_file(part).unwrap();
+    }
+
+    #[test]
+    fn test_album_manifest() {
+        let output = std::env::temp_dir().join(format!("gdog-manifest-{}", std::process::id()));
+        std::fs::create_dir_all(&output).unwrap();
+        let mut processor = Processor::new("/tmp/unused", output.to_str().unwrap());
+        processor.albums.insert(
+            PathBuf::from("Mum_s 60th"),
+            Album::parse(r#"{"title": "Mum's 60th 🎉", "description": "At the bach", "date": {"timestamp": "1562241600"}, "location": "Raglan"}"#)
+                .unwrap(),
+        );
+        processor
+            .album_sources
+            .insert("Mum's 60th 🎉".to_owned(), PathBuf::from("Mum_s 60th"));
+
+        let party = AlbumMembership {
+            album: "Mum's 60th 🎉".to_owned(),
+            shared: false,
+        };
+        let cousins = AlbumMembership {
+            album: "Cousins".to_owned(),
+            shared: true,
+        };
+        for (name, date, albums) in [
+            (
+                "albums/Mum's 60th 🎉/Speech.mp4",
+                Some(1_562_241_000),
+                vec![party.clone()],
+            ),
+            (
+                "albums/Mum's 60th 🎉/Cake.jpg",
+                Some(1_562_242_000),
+                vec![party.clone(), cousins.clone()],
+            ),
+            ("albums/Mum's 60th 🎉/Blurry.jpg", None, vec![party.clone()]),
+            (
+                "shared/Cousins/Beach.jpg",
+                Some(1_562_243_000),
+                vec![cousins.clone()],
+            ),
+        ] {
+            processor.media_files.push(MediaFile {
+                media_path: PathBuf::from(name),
+                destination_path: Some(output.join(name)),
+                destination_type: None,
+                json_path: None,
+                media_creation_date: date
+                    .map(|date| DateTime::from_timestamp(date, 0).unwrap().into()),
+                match_source: MatchSource::JsonFile,
+                fuzzy_candidates: Vec::new(),
+                fuzzy_conflicts: Vec::new(),
+                albums,
+                album_copies: Vec::new(),
+            });
+        }
+
+        processor.write_album_manifest().unwrap();
+        let manifest: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(output.join("albums.json")).unwrap())
+                .unwrap();
+
+        let party = &manifest["Mum's 60th 🎉"];
+        assert_eq!(party["title"], "Mum's 60th 🎉");
+        assert_eq!(party["description"], "At the bach");
+        assert_eq!(party["date"], "2019-07-04T12:00:00Z");
+        assert_eq!(party["location"], "Raglan");
+        assert_eq!(party["shared"], false);
+        assert_eq!(party["folder"], "albums/Mum's 60th 🎉");
+        // videos only make the cover when there's nothing else
+        assert_eq!(party["cover"], "albums/Mum's 60th 🎉/Cake.jpg");
+        assert_eq!(
+            party["files"],
+            serde_json::json!([
+                "albums/Mum's 60th 🎉/Speech.mp4",
+                "albums/Mum's 60th 🎉/Cake.jpg",
+                "albums/Mum's 60th 🎉/Blurry.jpg",
+            ])
+        );
+
+        // no metadata, so the folder name stands in for the title
+        let cousins = &manifest["Cousins"];
+        assert_eq!(cousins["title"], "Cousins");
+        assert_eq!(cousins["date"], serde_json::Value::Null);
+        assert!(cousins.get("location").is_none());
+        assert_eq!(cousins["shared"], true);
+        assert_eq!(cousins["folder"], "shared/Cousins");
+        assert_eq!(
+            cousins["files"],
+            serde_json::json!(["albums/Mum's 60th 🎉/Cake.jpg", "shared/Cousins/Beach.jpg"])
+        );
+
+        std::fs::remove_dir_all(output).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_unreadable_files_are_skipped() {
+        let part = write_takeout_part(
+            "takeout-unreadable",
+            &[("Takeout/Google Photos/Trip/fine.MTS", b"a fine clip")],
+        );
+        // stored rather than compressed, so the broken byte is only noticed by the checksum at the end. That is
+        // while finding its type for a file shorter than the header, and while hashing it for a longer one.
+        let broken =
+            std::env::temp_dir().join(format!("takeout-broken-{}.zip", std::process::id()));
+        let mut writer = ZipWriter::new(std::fs::File::create(&broken).unwrap());
+        let options = FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+        writer
+            .start_file("Takeout/Google Photos/Trip/broken.MTS", options)
+            .unwrap();
+        writer.write_all(&[b'x'; 8192]).unwrap();
+        writer
+            .start_file("Takeout/Google Photos/Trip/short.MTS", options)
+            .unwrap();
+        writer.write_all(&[b'z'; 100]).unwrap();
+        writer.finish().unwrap();
+        let mut bytes = std::fs::read(&broken).unwrap();
+        let start = bytes.windows(8192).position(|w| w == [b'x'; 8192]).unwrap();
+        bytes[start + 6000] = b'y';
+        let start = bytes.windows(100).position(|w| w == [b'z'; 100]).unwrap();
+        bytes[start + 2] = b'y';
+        std::fs::write(&broken, bytes).unwrap();
+
+        let mut processor = Processor::new(part.to_str().unwrap(), "/tmp/unused");
+        processor.add_input(broken.to_str().unwrap());
+        processor.load_files().unwrap();
+        processor.generate_destination_paths().unwrap();
+        processor.remove_duplicates().await.unwrap();
+
+        assert_eq!(processor.media_files.len(), 1);
+        assert!(processor.media_files[0].media_path.ends_with("fine.MTS"));
+        let mut skipped: Vec<_> = processor
+            .skipped
+            .iter()
+            .map(|skipped| {
+                assert!(matches!(skipped.reason, SkipReason::Unreadable { .. }));
+                skipped.media_path.file_name().unwrap()
+            })
+            .collect();
+        skipped.sort();
+        assert_eq!(skipped, ["broken.MTS", "short.MTS"]);
+
+        std::fs::remove_file(part).unwrap();
+        std::fs::remove_file(broken).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_files_already_in_immich_are_skipped() {
+        let part = write_takeout_part(
+            "takeout-in-immich",
+            &[
+                ("Takeout/Google Photos/Trip/clip.MTS", b"not really a jpeg"),
+                (
+                    "Takeout/Google Photos/Photos from 2019/clip.MTS",
+                    b"not really a jpeg",
+                ),
+                ("Takeout/Google Photos/Trip/scan.MTS", b"old scan"),
+                ("Takeout/Google Photos/Trip/other.MTS", b"another clip"),
+                ("Takeout/Google Photos/Beach/other.MTS", b"another clip"),
+            ],
+        );
+        let checksums = part.with_extension("checksums");
+        // the sha-1 of "old scan", as base64
+        std::fs::write(&checksums, "cw/+5YH4oDrVZcad4fMqTXrzvgI=\n").unwrap();
+
+        let mut processor = Processor::new(part.to_str().unwrap(), "/tmp/unused");
+        processor.skip_checksums_in(&checksums).unwrap();
+        processor.immich_assets.insert(
+            "5a75328649a4df2eb64f0e10f6912f612094e8f4".to_owned(),
+            Some("asset-1".to_owned()),
+        );
+        processor.load_files().unwrap();
+        processor.generate_destination_paths().unwrap();
+        processor.remove_duplicates().await.unwrap();
+
+        assert_eq!(processor.media_files.len(), 1);
+        assert!(processor.media_files[0]
+            .media_path
+            .ends_with("Beach/other.MTS"));
+
+        let reason = |name: &str| {
+            let skipped = processor
+                .skipped
+                .iter()
+                .find(|skipped| skipped.media_path.ends_with(name))
+                .unwrap();
+            serde_json::to_value(&skipped.reason).unwrap()
+        };
+        assert_eq!(
+            reason("Trip/clip.MTS"),
+            serde_json::json!({"InImmich": {"asset_id": "asset-1", "album": {"album": "Trip", "shared": false}}})
+        );
+        assert_eq!(
+            reason("Photos from 2019/clip.MTS"),
+            serde_json::json!({"InImmich": {"asset_id": "asset-1", "album": null}})
+        );
+        assert_eq!(
+            reason("Trip/scan.MTS"),
+            serde_json::json!({"InImmich": {"asset_id": null, "album": {"album": "Trip", "shared": false}}})
+        );
         assert_eq!(
-        json_path,
-        PathBuf::from("/home/josiah/Documents/g-takeout-processor/gdog/takeout/Google Photos/Photos from 2018/2018-06-17 01_54_22-13th June - OneNote 2016.png(1).json")
-    );
+            reason("Trip/other.MTS"),
+            serde_json::json!({"Duplicate": {"kept": "Takeout/Google Photos/Beach/other.MTS"}})
+        );
+
+        // what immich already has is still added to the album when uploading
+        let manifest = processor.album_manifest();
+        assert_eq!(manifest["Trip"].in_immich, ["asset-1"]);
+        assert_eq!(manifest["Trip"].files.len(), 1);
+
+        std::fs::remove_file(checksums).unwrap();
+        std::fs::remove_file(part).unwrap();
     }
 }
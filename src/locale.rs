@@ -0,0 +1,269 @@
+/// The names Google uses for the special folders and files of a takeout, in a single language.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Locale {
+    pub code: &'static str,
+    /// folder holding archived photos, e.g. "Archive"
+    pub archive: &'static str,
+    /// year folders are named `{prefix}YYYY`, e.g. "Photos from 2019"
+    pub year_folder_prefix: &'static str,
+    /// shared albums without a title are named `{untitled}` or `{untitled}(x)`
+    pub untitled: &'static str,
+    /// the per-album metadata file, e.g. "metadata.json"
+    pub album_metadata: &'static str,
+    /// json files at the top of the export that are neither media nor sidecars
+    pub ignored_files: &'static [&'static str],
+}
+
+pub const LOCALES: &[Locale] = &[
+    Locale {
+        code: "en",
+        archive: "Archive",
+        year_folder_prefix: "Photos from ",
+        untitled: "Untitled",
+        album_metadata: "metadata.json",
+        ignored_files: &[
+            "shared_album_comments.json",
+            "user-generated-memory-titles.json",
+            "print-subscriptions.json",
+        ],
+    },
+    Locale {
+        code: "de",
+        archive: "Archiv",
+        year_folder_prefix: "Fotos von ",
+        untitled: "Ohne Titel",
+        album_metadata: "Metadaten.json",
+        ignored_files: &[
+            "Kommentare_zu_geteilten_Alben.json",
+            "von-Nutzern-erstellte-Titel-für-Rückblicke.json",
+            "Druck-Abos.json",
+        ],
+    },
+    Locale {
+        code: "fr",
+        archive: "Archives",
+        year_folder_prefix: "Photos de ",
+        untitled: "Sans titre",
+        album_metadata: "métadonnées.json",
+        ignored_files: &[
+            "commentaires_albums_partagés.json",
+            "titres-de-souvenirs-créés-par-l-utilisateur.json",
+            "abonnements-impression.json",
+        ],
+    },
+    Locale {
+        code: "es",
+        archive: "Archivo",
+        year_folder_prefix: "Fotos de ",
+        untitled: "Sin título",
+        album_metadata: "metadatos.json",
+        ignored_files: &[
+            "comentarios_álbumes_compartidos.json",
+            "títulos-de-recuerdos-creados-por-el-usuario.json",
+            "suscripciones-de-impresión.json",
+        ],
+    },
+    Locale {
+        code: "it",
+        archive: "Archivio",
+        year_folder_prefix: "Foto dal ",
+        untitled: "Senza titolo",
+        album_metadata: "metadati.json",
+        ignored_files: &[
+            "commenti_album_condivisi.json",
+            "titoli-ricordi-creati-dall-utente.json",
+            "abbonamenti-stampa.json",
+        ],
+    },
+    Locale {
+        code: "pt",
+        archive: "Arquivo",
+        year_folder_prefix: "Fotos de ",
+        untitled: "Sem título",
+        album_metadata: "metadados.json",
+        ignored_files: &[
+            "comentários_álbuns_compartilhados.json",
+            "títulos-de-memórias-criados-pelo-usuário.json",
+            "assinaturas-de-impressão.json",
+        ],
+    },
+    Locale {
+        code: "nl",
+        archive: "Archief",
+        year_folder_prefix: "Foto's uit ",
+        untitled: "Zonder titel",
+        album_metadata: "metadata.json",
+        ignored_files: &[
+            "reacties_gedeelde_albums.json",
+            "door-gebruiker-gemaakte-herinneringstitels.json",
+            "afdrukabonnementen.json",
+        ],
+    },
+    Locale {
+        code: "pl",
+        archive: "Archiwum",
+        year_folder_prefix: "Zdjęcia z ",
+        untitled: "Bez tytułu",
+        album_metadata: "metadane.json",
+        ignored_files: &[
+            "komentarze_udostępnionych_albumów.json",
+            "tytuły-wspomnień-utworzone-przez-użytkownika.json",
+            "subskrypcje-wydruków.json",
+        ],
+    },
+    Locale {
+        code: "ru",
+        archive: "Архив",
+        year_folder_prefix: "Фото за ",
+        untitled: "Без названия",
+        album_metadata: "метаданные.json",
+        ignored_files: &[
+            "комментарии_к_общим_альбомам.json",
+            "названия-воспоминаний-созданные-пользователем.json",
+            "подписки-на-печать.json",
+        ],
+    },
+];
+
+/// look up a locale by its language code, e.g. "de"
+pub fn by_code(code: &str) -> Option<&'static Locale> {
+    LOCALES.iter().find(|locale| locale.code == code)
+}
+
+/// check if a file is the album metadata file of any locale, for use before the locale is known
+pub fn is_album_metadata(file_name: &str) -> bool {
+    LOCALES
+        .iter()
+        .any(|locale| eq_ignore_case(locale.album_metadata, file_name))
+}
+
+/// check if a file is one of the files that are neither media nor sidecars in any locale, for use before the
+/// locale is known
+pub fn is_ignored_file(file_name: &str) -> bool {
+    LOCALES.iter().any(|locale| {
+        locale
+            .ignored_files
+            .iter()
+            .any(|ignored| eq_ignore_case(ignored, file_name))
+    })
+}
+
+/// google isn't consistent about the case of its names, e.g. "Photos from 2019" and "Photos From 2019"
+fn eq_ignore_case(a: &str, b: &str) -> bool {
+    a.to_lowercase() == b.to_lowercase()
+}
+
+/// `name` without `prefix`, ignoring case
+fn strip_prefix_ignore_case<'n>(name: &'n str, prefix: &str) -> Option<&'n str> {
+    let (split, _) = name
+        .char_indices()
+        .chain([(name.len(), ' ')])
+        .nth(prefix.chars().count())?;
+    eq_ignore_case(&name[..split], prefix).then(|| &name[split..])
+}
+
+impl Locale {
+    /// "Photos from 2019" and friends, which hold photos that aren't in any album
+    pub fn is_year_folder(&self, folder_name: &str) -> bool {
+        strip_prefix_ignore_case(folder_name, self.year_folder_prefix)
+            .is_some_and(|year| year.len() == 4 && year.chars().all(|c| c.is_ascii_digit()))
+    }
+
+    pub fn is_archive_folder(&self, folder_name: &str) -> bool {
+        eq_ignore_case(folder_name, self.archive)
+    }
+
+    /// "Untitled" and "Untitled(x)", which hold shared albums
+    pub fn is_untitled_folder(&self, folder_name: &str) -> bool {
+        strip_prefix_ignore_case(folder_name, self.untitled)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('('))
+    }
+
+    /// Pick the locale an export was made in, by counting how many of the folder and file names in it match
+    /// each locale. Falls back to English if nothing matches at all.
+    pub fn detect<'n>(names: impl Iterator<Item = &'n str>) -> &'static Locale {
+        let mut scores = vec![0; LOCALES.len()];
+        for name in names {
+            for (locale, score) in LOCALES.iter().zip(scores.iter_mut()) {
+                if locale.is_year_folder(name)
+                    || locale.is_archive_folder(name)
+                    || locale.is_untitled_folder(name)
+                    || eq_ignore_case(locale.album_metadata, name)
+                {
+                    *score += 1;
+                }
+            }
+        }
+
+        // take the first locale with the highest score, so english wins any ties
+        let mut best = 0;
+        for (i, score) in scores.iter().enumerate() {
+            if *score > scores[best] {
+                best = i;
+            }
+        }
+        &LOCALES[best]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{by_code, is_album_metadata, is_ignored_file, Locale};
+
+    #[test]
+    fn test_detect_german_export() {
+        let names = [
+            "Fotos von 2019",
+            "Fotos von 2020",
+            "Archiv",
+            "Urlaub in Rom",
+            "Metadaten.json",
+        ];
+        let locale = Locale::detect(names.into_iter());
+        assert_eq!(locale.code, "de");
+        assert!(locale.is_year_folder("Fotos von 2019"));
+        assert!(!locale.is_year_folder("Fotos von Oma"));
+    }
+
+    #[test]
+    fn test_detect_falls_back_to_english() {
+        let locale = Locale::detect(["Beach trip", "IMG_0001.jpg"].into_iter());
+        assert_eq!(locale, by_code("en").unwrap());
+    }
+
+    #[test]
+    fn test_untitled_folders() {
+        let french = by_code("fr").unwrap();
+        assert!(french.is_untitled_folder("Sans titre"));
+        assert!(french.is_untitled_folder("Sans titre(3)"));
+        assert!(!french.is_untitled_folder("Sans titre ni fin"));
+        assert!(is_album_metadata("métadonnées.json"));
+        assert!(is_album_metadata("metadata.json"));
+    }
+
+    #[test]
+    fn test_names_ignore_case() {
+        let english = by_code("en").unwrap();
+        assert!(english.is_year_folder("Photos From 2019"));
+        assert!(english.is_year_folder("photos from 2019"));
+        assert!(english.is_archive_folder("ARCHIVE"));
+        assert!(english.is_untitled_folder("untitled(2)"));
+        assert!(!english.is_year_folder("Photos"));
+
+        let russian = by_code("ru").unwrap();
+        assert!(russian.is_year_folder("ФОТО ЗА 2019"));
+        assert_eq!(
+            Locale::detect(["Фото за 2019", "фото за 2020", "METADATA.JSON"].into_iter()),
+            russian
+        );
+    }
+
+    #[test]
+    fn test_ignored_files_in_any_language() {
+        assert!(is_ignored_file("print-subscriptions.json"));
+        assert!(is_ignored_file("Shared_Album_Comments.json"));
+        assert!(is_ignored_file("Kommentare_zu_geteilten_Alben.json"));
+        assert!(is_ignored_file("abonnements-impression.json"));
+        assert!(!is_ignored_file("IMG_0001.jpg.json"));
+    }
+}
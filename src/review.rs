@@ -0,0 +1,267 @@
+use std::{
+    collections::BTreeMap,
+    io::{self, BufRead, Write},
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::FuzzyCandidate;
+
+/// What the user decided about the fuzzy match of a media file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Decision {
+    /// use this sidecar, which is either the proposed match or an alternative the user picked
+    Accepted { json_path: PathBuf },
+    /// none of the candidates belong to the media file
+    Rejected,
+}
+
+/// Review decisions, saved to a json file after every answer so a re-run never asks the same question twice
+#[derive(Debug)]
+pub struct Decisions {
+    path: PathBuf,
+    decisions: BTreeMap<PathBuf, Decision>,
+}
+
+impl Decisions {
+    /// load previous decisions, starting afresh if the file doesn't exist yet
+    pub fn load(path: &Path) -> Result<Decisions, Box<dyn std::error::Error>> {
+        let decisions = if path.exists() {
+            serde_json::from_str(&std::fs::read_to_string(path)?)?
+        } else {
+            BTreeMap::new()
+        };
+
+        Ok(Decisions {
+            path: path.to_path_buf(),
+            decisions,
+        })
+    }
+
+    pub fn get(&self, media_path: &Path) -> Option<&Decision> {
+        self.decisions.get(media_path)
+    }
+
+    /// record a decision and write the file straight away, so nothing is lost if the review is cut short
+    pub fn record(
+        &mut self,
+        media_path: PathBuf,
+        decision: Decision,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.decisions.insert(media_path, decision);
+        self.save()
+    }
+
+    /// forget the decision about a media file, so it is asked about again
+    pub fn remove(&mut self, media_path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        if self.decisions.remove(media_path).is_some() {
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
+        std::fs::write(&self.path, serde_json::to_string_pretty(&self.decisions)?)?;
+        Ok(())
+    }
+}
+
+/// Show a fuzzy match and its alternatives, and ask the user what to do with it. `proposed` is the index of the
+/// candidate the file was matched to, which is what an empty answer accepts. Without one, an empty answer
+/// rejects them all.
+pub fn ask(
+    input: &mut dyn BufRead,
+    output: &mut dyn Write,
+    progress: (usize, usize),
+    media_path: &Path,
+    candidates: &[FuzzyCandidate],
+    proposed: Option<usize>,
+) -> io::Result<Decision> {
+    writeln!(
+        output,
+        "[{}/{}] Fuzzy match for {}",
+        progress.0,
+        progress.1,
+        media_path.display()
+    )?;
+    for (i, candidate) in candidates.iter().enumerate() {
+        writeln!(
+            output,
+            "  {}) score[{}] {}",
+            i + 1,
+            candidate.score,
+            candidate.json_path.display()
+        )?;
+    }
+
+    loop {
+        match proposed {
+            Some(proposed) => write!(
+                output,
+                "Accept {}? [y]es, [n]o, or the number of another match: ",
+                proposed + 1
+            )?,
+            None => write!(
+                output,
+                "No match was made, pick one by its number or [n]o: "
+            )?,
+        }
+        output.flush()?;
+
+        let mut answer = String::new();
+        if input.read_line(&mut answer)? == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "review was cut short",
+            ));
+        }
+
+        let picked = match (answer.trim().to_lowercase().as_str(), proposed) {
+            ("" | "y" | "yes", Some(proposed)) => Some(proposed),
+            ("" | "n" | "no", _) => return Ok(Decision::Rejected),
+            (number, _) => number
+                .parse::<usize>()
+                .ok()
+                .filter(|n| (1..=candidates.len()).contains(n))
+                .map(|n| n - 1),
+        };
+
+        match picked {
+            Some(i) => {
+                return Ok(Decision::Accepted {
+                    json_path: candidates[i].json_path.clone(),
+                })
+            }
+            None => writeln!(output, "Please answer y, n or 1-{}", candidates.len())?,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::Cursor,
+        path::{Path, PathBuf},
+    };
+
+    use super::{ask, Decision, Decisions};
+    use crate::FuzzyCandidate;
+
+    #[test]
+    fn test_ask_picks_an_alternative() {
+        let candidates = [
+            FuzzyCandidate {
+                json_path: PathBuf::from("Trip/IMG_0001.jpg.json"),
+                score: 92,
+            },
+            FuzzyCandidate {
+                json_path: PathBuf::from("Trip/IMG_0001(1).jpg.json"),
+                score: 90,
+            },
+        ];
+
+        let mut input = Cursor::new("maybe\n3\n2\n");
+        let mut output = Vec::new();
+        let decision = ask(
+            &mut input,
+            &mut output,
+            (1, 1),
+            Path::new("Trip/IMG_0001 (1).jpg"),
+            &candidates,
+            Some(0),
+        )
+        .unwrap();
+
+        assert_eq!(
+            decision,
+            Decision::Accepted {
+                json_path: PathBuf::from("Trip/IMG_0001(1).jpg.json")
+            }
+        );
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("score[92] Trip/IMG_0001.jpg.json"));
+        assert_eq!(output.matches("Please answer").count(), 2);
+
+        let mut input = Cursor::new("n\n");
+        let decision = ask(
+            &mut input,
+            &mut Vec::new(),
+            (1, 1),
+            Path::new("Trip/IMG_0001 (1).jpg"),
+            &candidates,
+            Some(0),
+        )
+        .unwrap();
+        assert_eq!(decision, Decision::Rejected);
+
+        // an empty answer takes the match that was made, which isn't always the best scoring candidate
+        let decision = ask(
+            &mut Cursor::new("\n"),
+            &mut Vec::new(),
+            (1, 1),
+            Path::new("Trip/IMG_0001 (1).jpg"),
+            &candidates,
+            Some(1),
+        )
+        .unwrap();
+        assert_eq!(
+            decision,
+            Decision::Accepted {
+                json_path: PathBuf::from("Trip/IMG_0001(1).jpg.json")
+            }
+        );
+
+        // and rejects them all when no match was made
+        let decision = ask(
+            &mut Cursor::new("\n"),
+            &mut Vec::new(),
+            (1, 1),
+            Path::new("Trip/IMG_0001 (1).jpg"),
+            &candidates,
+            None,
+        )
+        .unwrap();
+        assert_eq!(decision, Decision::Rejected);
+    }
+
+    #[test]
+    fn test_decisions_are_persisted() {
+        let path = std::env::temp_dir().join(format!("gdog-decisions-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let mut decisions = Decisions::load(&path).unwrap();
+        assert!(decisions.get(Path::new("Trip/a.jpg")).is_none());
+        decisions
+            .record(PathBuf::from("Trip/a.jpg"), Decision::Rejected)
+            .unwrap();
+        decisions
+            .record(
+                PathBuf::from("Trip/b.jpg"),
+                Decision::Accepted {
+                    json_path: PathBuf::from("Trip/b.jpg.json"),
+                },
+            )
+            .unwrap();
+
+        let reloaded = Decisions::load(&path).unwrap();
+        assert_eq!(
+            reloaded.get(Path::new("Trip/a.jpg")),
+            Some(&Decision::Rejected)
+        );
+        assert_eq!(
+            reloaded.get(Path::new("Trip/b.jpg")),
+            Some(&Decision::Accepted {
+                json_path: PathBuf::from("Trip/b.jpg.json")
+            })
+        );
+
+        decisions.remove(Path::new("Trip/a.jpg")).unwrap();
+        let reloaded = Decisions::load(&path).unwrap();
+        assert!(reloaded.get(Path::new("Trip/a.jpg")).is_none());
+        assert!(reloaded.get(Path::new("Trip/b.jpg")).is_some());
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,404 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{split_duplicate_counter, truncation};
+
+/// Newer takeouts name sidecars `IMG_1234.JPG.supplemental-metadata.json` instead of `IMG_1234.JPG.json`
+pub const SUPPLEMENTAL_METADATA: &str = "supplemental-metadata";
+
+/// Suffixes google adds to the edited copy of a photo, which shares the sidecar of the original
+const EDITED_SUFFIXES: &[&str] = &[
+    "-edited",
+    "-bearbeitet",
+    "-modifié",
+    "-editado",
+    "-modificato",
+    "-bewerkt",
+    "-edytowane",
+    "-изменено",
+];
+
+/// The parts of a takeout json sidecar we care about
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Sidecar {
+    /// the original filename of the media file, before google renamed or truncated it
+    pub title: String,
+    #[serde(rename = "photoTakenTime", default)]
+    pub photo_taken_time: Option<Timestamp>,
+    #[serde(default)]
+    pub favorited: bool,
+    #[serde(default)]
+    pub archived: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Timestamp {
+    /// unix time in seconds, as a string
+    pub timestamp: String,
+}
+
+impl Timestamp {
+    pub fn to_date(&self) -> Option<DateTime<Utc>> {
+        DateTime::from_timestamp(self.timestamp.parse().ok()?, 0)
+    }
+}
+
+impl Sidecar {
+    /// when the photo was taken, if google knows
+    pub fn taken_at(&self) -> Option<DateTime<Utc>> {
+        self.photo_taken_time.as_ref()?.to_date()
+    }
+}
+
+/// (normalised name, duplicate counter)
+type NameKey = (String, Option<u32>);
+
+/// How a sidecar name was turned into a key, more exact kinds win when two sidecars share a key
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum NameKind {
+    Exact,
+    Supplemental,
+}
+
+#[derive(Debug, Default)]
+struct DirectoryIndex {
+    by_title: HashMap<NameKey, PathBuf>,
+    by_name: HashMap<NameKey, (NameKind, PathBuf)>,
+    by_file_name: HashMap<String, PathBuf>,
+    /// sidecars whose name google cut short, these can only be found by prefix
+    truncated: Vec<(NameKey, PathBuf)>,
+}
+
+/// Resolves the sidecar of a media file with a handful of hash lookups, without touching the disk.
+///
+/// Every sidecar in the takeout is indexed per directory twice: once by the `title` stored inside it, which
+/// is the original name of the media file, and once by its own file name with everything google appends
+/// (`.json`, `(n)`, `.supplemental-metadata`) stripped off.
+#[derive(Debug, Default)]
+pub struct SidecarIndex {
+    sidecars: HashMap<PathBuf, Sidecar>,
+    directories: HashMap<PathBuf, DirectoryIndex>,
+    /// every sidecar in the takeout by its normalised title, for files whose sidecar is in another directory
+    titles: HashMap<String, Vec<PathBuf>>,
+}
+
+/// Google replaces characters that aren't allowed in filenames, so do the same to titles and names before
+/// comparing. Doubled dots are collapsed, as google is inconsistent about `photo..jpg` vs `photo.jpg`.
+fn normalise(name: &str) -> String {
+    let mut normalised = String::with_capacity(name.len());
+    for c in name.chars() {
+        let c = match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' | '\'' => '_',
+            c => c,
+        };
+        if c == '.' && normalised.ends_with('.') {
+            continue;
+        }
+        normalised.push(c);
+    }
+    normalised.to_lowercase()
+}
+
+/// The stems a media file may have been uploaded with, with the duplicate counter that goes with each. A stem
+/// ending in `(n)` is usually the nth duplicate, but could also just be the original name, and edited photos
+/// share the sidecar of the original.
+fn original_stems(file_stem: &str) -> Vec<(String, Option<u32>)> {
+    let mut stems = Vec::with_capacity(2);
+    if let Some((base, number)) = split_duplicate_counter(file_stem) {
+        stems.push((base.to_owned(), Some(number)));
+    }
+    stems.push((file_stem.to_owned(), None));
+
+    for i in 0..stems.len() {
+        let (stem, counter) = stems[i].clone();
+        for suffix in EDITED_SUFFIXES {
+            if let Some(original) = stem.strip_suffix(suffix) {
+                stems.push((original.to_owned(), counter));
+            }
+        }
+    }
+    stems
+}
+
+/// strip a (possibly truncated) `.supplemental-metadata` off the end of a sidecar name
+fn strip_supplemental(name: &str) -> Option<&str> {
+    let (base, suffix) = name.rsplit_once('.')?;
+    (!suffix.is_empty() && SUPPLEMENTAL_METADATA.starts_with(suffix)).then_some(base)
+}
+
+impl SidecarIndex {
+    fn directory(&mut self, json_path: &Path) -> &mut DirectoryIndex {
+        self.directories
+            .entry(json_path.parent().unwrap().to_path_buf())
+            .or_default()
+    }
+
+    /// index a sidecar by its file name, this works even if the sidecar can't be parsed
+    pub fn insert_name(&mut self, json_path: &Path) {
+        let file_name = json_path.file_name().unwrap().to_str().unwrap();
+        let Some(name) = file_name
+            .strip_suffix(".json")
+            .or_else(|| file_name.strip_suffix(".JSON"))
+        else {
+            return;
+        };
+
+        // the `(n)` of a duplicate always ends up right before `.json`
+        let (name, counter) = match split_duplicate_counter(name) {
+            Some((name, number)) => (name, Some(number)),
+            None => (name, None),
+        };
+
+        let mut keys = vec![(NameKind::Exact, name)];
+        if let Some(stripped) = strip_supplemental(name) {
+            keys.push((NameKind::Supplemental, stripped));
+        }
+
+        let is_truncated = name.chars().count() == truncation::SIDECAR_NAME_CHARS;
+        let directory = self.directory(json_path);
+        directory
+            .by_file_name
+            .insert(normalise(file_name), json_path.to_path_buf());
+        if is_truncated {
+            directory
+                .truncated
+                .push(((normalise(name), counter), json_path.to_path_buf()));
+        }
+        for (kind, name) in keys {
+            let candidate = (kind, json_path.to_path_buf());
+            directory
+                .by_name
+                .entry((normalise(name), counter))
+                .and_modify(|existing| {
+                    if candidate < *existing {
+                        *existing = candidate.clone();
+                    }
+                })
+                .or_insert(candidate);
+        }
+    }
+
+    /// index a parsed sidecar by the title stored inside it
+    pub fn insert(&mut self, json_path: PathBuf, sidecar: Sidecar) {
+        // the `(n)` of a duplicate is only in the name of the sidecar, the title is the same for every copy
+        let json_stem = json_path.file_stem().unwrap().to_str().unwrap();
+        let counter = split_duplicate_counter(json_stem).map(|(_, number)| number);
+
+        // sidecars are parsed in no particular order, so when several in a directory share a title the shortest
+        // name wins, as the others are usually a `.supplemental-metadata` copy or an edit
+        let title = normalise(&sidecar.title);
+        let sort_key = |path: &Path| (path.as_os_str().len(), path.to_path_buf());
+        self.directory(&json_path)
+            .by_title
+            .entry((title.clone(), counter))
+            .and_modify(|existing| {
+                if sort_key(&json_path) < sort_key(existing) {
+                    *existing = json_path.clone();
+                }
+            })
+            .or_insert_with(|| json_path.clone());
+        self.titles
+            .entry(title)
+            .or_default()
+            .push(json_path.clone());
+        self.sidecars.insert(json_path, sidecar);
+    }
+
+    pub fn get(&self, json_path: &Path) -> Option<&Sidecar> {
+        self.sidecars.get(json_path)
+    }
+
+    /// find sidecars anywhere in the takeout whose title is the name of a media file, ignoring duplicate counters
+    /// as those are only unique within a directory
+    pub fn find_by_title(&self, media_path: &Path) -> Vec<&PathBuf> {
+        let (Some(file_stem), Some(file_ext)) = (
+            media_path.file_stem().and_then(|stem| stem.to_str()),
+            media_path.extension().and_then(|ext| ext.to_str()),
+        ) else {
+            return Vec::new();
+        };
+
+        let mut found: Vec<&PathBuf> = Vec::new();
+        for (stem, _) in original_stems(file_stem) {
+            let title = normalise(&format!("{}.{}", stem, file_ext));
+            for json_path in self.titles.get(&title).into_iter().flatten() {
+                if !found.contains(&json_path) {
+                    found.push(json_path);
+                }
+            }
+        }
+        found.sort();
+        found
+    }
+
+    /// find the sidecar sitting in the same directory as a media file
+    pub fn resolve(&self, media_path: &Path) -> Option<&PathBuf> {
+        let directory = self.directories.get(media_path.parent()?)?;
+        let file_stem = media_path.file_stem()?.to_str()?;
+        let file_ext = media_path.extension()?.to_str()?;
+
+        let stems = original_stems(file_stem);
+
+        // 1. the title inside the sidecar is the original file name
+        for (stem, counter) in &stems {
+            let key = (normalise(&format!("{}.{}", stem, file_ext)), *counter);
+            if let Some(found) = directory.by_title.get(&key) {
+                return Some(found);
+            }
+        }
+
+        // 2. `IMG_1234.jpg.json`, or for heic files `IMG_1234.json`
+        let is_heic = file_ext.eq_ignore_ascii_case("heic");
+        let mut names = Vec::with_capacity(stems.len() * 2);
+        for (stem, counter) in &stems {
+            let with_ext = (normalise(&format!("{}.{}", stem, file_ext)), *counter);
+            let without_ext = (normalise(stem), *counter);
+            if is_heic {
+                names.extend([without_ext, with_ext]);
+            } else {
+                names.extend([with_ext, without_ext]);
+            }
+        }
+        for key in &names {
+            if let Some((_, found)) = directory.by_name.get(key) {
+                return Some(found);
+            }
+        }
+
+        // 3. google cut the name of the sidecar, and maybe the media file too, see `truncation`
+        for (stem, counter) in &stems {
+            if truncation::is_truncated_media_name(stem, file_ext) {
+                let prefix = normalise(stem);
+                let found = directory
+                    .truncated
+                    .iter()
+                    .filter(|((name, c), _)| c == counter && name.starts_with(&prefix))
+                    .map(|(_, path)| path)
+                    .min();
+                if found.is_some() {
+                    return found;
+                }
+                continue;
+            }
+
+            let original = format!("{}.{}", stem, file_ext);
+            for supplemental in [false, true] {
+                let name = truncation::sidecar_name(&original, supplemental, *counter);
+                if let Some(found) = directory.by_file_name.get(&normalise(&name)) {
+                    return Some(found);
+                }
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::{Sidecar, SidecarIndex};
+
+    fn index(sidecars: &[(&str, &str)]) -> SidecarIndex {
+        let mut index = SidecarIndex::default();
+        for (path, title) in sidecars {
+            index.insert_name(Path::new(path));
+            index.insert(
+                PathBuf::from(path),
+                Sidecar {
+                    title: title.to_string(),
+                    photo_taken_time: None,
+                    favorited: false,
+                    archived: false,
+                },
+            );
+        }
+        index
+    }
+
+    #[test]
+    fn test_resolve_by_title() {
+        let index = index(&[
+            ("/takeout/Trip/IMG_0001.JPG.json", "IMG_0001.JPG"),
+            ("/takeout/Trip/IMG_0001.JPG(1).json", "IMG_0001.JPG"),
+            // google cut this sidecar's name short, but the title is whole
+            (
+                "/takeout/Trip/A really long name for a photo that google c.json",
+                "A really long name for a photo that google cut short.jpg",
+            ),
+        ]);
+
+        let resolve = |media: &str| index.resolve(Path::new(media)).map(|p| p.to_str().unwrap());
+        assert_eq!(
+            resolve("/takeout/Trip/IMG_0001.JPG"),
+            Some("/takeout/Trip/IMG_0001.JPG.json")
+        );
+        assert_eq!(
+            resolve("/takeout/Trip/IMG_0001(1).JPG"),
+            Some("/takeout/Trip/IMG_0001.JPG(1).json")
+        );
+        assert_eq!(
+            resolve("/takeout/Trip/IMG_0001-edited.JPG"),
+            Some("/takeout/Trip/IMG_0001.JPG.json")
+        );
+        assert_eq!(
+            resolve("/takeout/Trip/A really long name for a photo that google cut short.jpg"),
+            Some("/takeout/Trip/A really long name for a photo that google c.json")
+        );
+        assert_eq!(resolve("/takeout/Other/IMG_0001.JPG"), None);
+    }
+
+    #[test]
+    fn test_shared_titles_resolve_the_same_either_way() {
+        let sidecars = [
+            (
+                "/takeout/Trip/IMG_0003.jpg.supplemental-metadata.json",
+                "IMG_0003.jpg",
+            ),
+            ("/takeout/Trip/IMG_0003.jpg.json", "IMG_0003.jpg"),
+        ];
+        let mut reversed = sidecars;
+        reversed.reverse();
+
+        for sidecars in [sidecars, reversed] {
+            let index = index(&sidecars);
+            assert_eq!(
+                index.resolve(Path::new("/takeout/Trip/IMG_0003.jpg")),
+                Some(&PathBuf::from("/takeout/Trip/IMG_0003.jpg.json"))
+            );
+        }
+    }
+
+    #[test]
+    fn test_resolve_heic_and_literal_brackets() {
+        let index = index(&[
+            ("/takeout/Trip/IMG_0002(1).json", "IMG_0002.HEIC"),
+            ("/takeout/Trip/party(1).jpg.json", "party(1).jpg"),
+            (
+                "/takeout/Trip/Mum_s birthday.jpg.json",
+                "Mum's birthday.jpg",
+            ),
+        ]);
+
+        let resolve = |media: &str| index.resolve(Path::new(media)).map(|p| p.to_str().unwrap());
+        assert_eq!(
+            resolve("/takeout/Trip/IMG_0002(1).HEIC"),
+            Some("/takeout/Trip/IMG_0002(1).json")
+        );
+        assert_eq!(resolve("/takeout/Trip/IMG_0002.HEIC"), None);
+        assert_eq!(
+            resolve("/takeout/Trip/party(1).jpg"),
+            Some("/takeout/Trip/party(1).jpg.json")
+        );
+        assert_eq!(
+            resolve("/takeout/Trip/Mum_s birthday.jpg"),
+            Some("/takeout/Trip/Mum_s birthday.jpg.json")
+        );
+    }
+}
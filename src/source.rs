@@ -0,0 +1,602 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, Cursor, Read},
+    path::{Component, Path, PathBuf},
+    sync::Mutex,
+};
+
+use flate2::read::GzDecoder;
+use sha1::Sha1;
+use sha3::{Digest, Sha3_256};
+use zip::ZipArchive;
+
+use crate::embedded_date::{self, EmbeddedDate, MediaReader};
+
+/// the folder at the top of every takeout part
+const TAKEOUT_ROOT: &str = "Takeout";
+
+/// how much of the start of each media file in a .tgz archive is kept around for file type detection
+const TAR_HEADER_LEN: u64 = 4 * 1024;
+
+/// Where the bytes of a single file in the takeout actually live
+#[derive(Debug)]
+enum EntryLocation {
+    /// a plain file on disk, stored by its real path
+    File(PathBuf),
+    /// an entry inside the zip archive at `archive`, stored by its name in the archive
+    Zip { archive: usize, name: String },
+    /// an entry inside a gzipped tar archive, which can only be read front to back
+    Tar(Box<TarEntry>),
+}
+
+/// Everything we learnt about a .tgz entry during the discovery pass, so it never has to be read again
+/// until it is copied out.
+#[derive(Debug)]
+struct TarEntry {
+    archive: usize,
+    /// the full contents, only kept for json sidecars
+    contents: Option<Vec<u8>>,
+    /// the first `TAR_HEADER_LEN` bytes of the file
+    header: Vec<u8>,
+    hashes: FileHashes,
+    /// the date in the file's own metadata, read on the way past while hashing it
+    embedded_date: Option<EmbeddedDate>,
+}
+
+/// A read-only view over every input of a takeout export.
+///
+/// Inputs may be extracted directories, `takeout-*.zip` archives or `takeout-*.tgz` archives. Large exports are
+/// split into many parts, and a photo's json sidecar often lands in a different part than the photo, so every
+/// input is overlaid into a single merged tree. Each file is addressed by its virtual path in that tree, which
+/// starts at the `Takeout` folder of its part (e.g. `Takeout/Google Photos/Album/IMG_1234.jpg`), or at the input
+/// itself if there is no `Takeout` folder.
+///
+/// Tar archives can't be read randomly, so they are read in exactly two passes: `add_tgz` streams the whole
+/// archive once, keeping json sidecars in memory and hashing every media file as it goes past, and `copy_all`
+/// streams it a second time to copy the media files out.
+#[derive(Default)]
+pub struct TakeoutFs {
+    zip_archives: Vec<Mutex<ZipArchive<File>>>,
+    tar_archives: Vec<PathBuf>,
+    entries: HashMap<PathBuf, EntryLocation>,
+    children: HashMap<PathBuf, Vec<PathBuf>>,
+}
+
+impl std::fmt::Debug for TakeoutFs {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TakeoutFs")
+            .field("zip_archives", &self.zip_archives.len())
+            .field("tar_archives", &self.tar_archives)
+            .field("entries", &self.entries.len())
+            .finish()
+    }
+}
+
+/// The hex digests of a file: sha3-256 to find duplicates within the takeout, and the sha-1 Immich knows assets by
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileHashes {
+    pub sha3: String,
+    pub sha1: String,
+}
+
+/// Hashes everything read through it, so a file can be parsed and hashed in a single read
+struct HashingReader<R> {
+    inner: R,
+    sha3: Sha3_256,
+    sha1: Sha1,
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.sha3.update(&buf[..n]);
+        self.sha1.update(&buf[..n]);
+        Ok(n)
+    }
+}
+
+impl<R: Read> HashingReader<R> {
+    fn new(inner: R) -> HashingReader<R> {
+        HashingReader {
+            inner,
+            sha3: Sha3_256::new(),
+            sha1: Sha1::new(),
+        }
+    }
+
+    /// hash the rest of the stream, returning the digests of all of it
+    fn finish(mut self) -> io::Result<FileHashes> {
+        io::copy(&mut self, &mut io::sink())?;
+        Ok(FileHashes {
+            sha3: format!("{:x}", self.sha3.finalize()),
+            sha1: format!("{:x}", self.sha1.finalize()),
+        })
+    }
+}
+
+/// hash a stream of bytes, see [`FileHashes`]
+pub fn hash_reader(reader: &mut dyn Read) -> io::Result<FileHashes> {
+    HashingReader::new(reader).finish()
+}
+
+/// map a path relative to the root of an input onto the merged tree, see [`TakeoutFs`]
+fn merged_path(relative: &Path) -> PathBuf {
+    let components: Vec<Component> = relative.components().collect();
+    match components
+        .iter()
+        .position(|c| c.as_os_str() == TAKEOUT_ROOT)
+    {
+        Some(root) => components[root..].iter().collect(),
+        None => relative.to_path_buf(),
+    }
+}
+
+/// only accept relative paths made of plain components, so nothing can escape the archive root
+fn is_enclosed(name: &Path) -> bool {
+    name.components().all(|c| matches!(c, Component::Normal(_)))
+}
+
+fn open_tgz(path: &Path) -> io::Result<tar::Archive<GzDecoder<File>>> {
+    Ok(tar::Archive::new(GzDecoder::new(File::open(path)?)))
+}
+
+impl TakeoutFs {
+    /// add a file to the merged tree, returning false if another part already provided the same path
+    fn insert(&mut self, virtual_path: &Path, location: EntryLocation) -> bool {
+        if self.entries.contains_key(virtual_path) {
+            println!(
+                "Skipping {}, it is present in more than one part of the takeout",
+                virtual_path.display()
+            );
+            return false;
+        }
+
+        if let Some(parent) = virtual_path.parent() {
+            self.children
+                .entry(parent.to_path_buf())
+                .or_default()
+                .push(virtual_path.to_path_buf());
+        }
+        self.entries.insert(virtual_path.to_path_buf(), location);
+        true
+    }
+
+    /// register a file found while walking the extracted directory `root`, returning its virtual path
+    pub fn add_file(&mut self, root: &Path, path: PathBuf) -> Option<PathBuf> {
+        let virtual_path = merged_path(path.strip_prefix(root).unwrap_or(&path));
+        self.insert(&virtual_path, EntryLocation::File(path))
+            .then_some(virtual_path)
+    }
+
+    /// open a zip archive and register all of its files, returning their virtual paths
+    pub fn add_zip(
+        &mut self,
+        archive_path: &Path,
+    ) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+        let archive = ZipArchive::new(File::open(archive_path)?)?;
+        let index = self.zip_archives.len();
+
+        let mut found = Vec::with_capacity(archive.len());
+        let mut names: Vec<&str> = archive.file_names().collect();
+        names.sort_unstable();
+        for name in names {
+            // skip directories, and anything that would escape the archive root
+            if name.ends_with('/') || !is_enclosed(Path::new(name)) {
+                continue;
+            }
+            let virtual_path = merged_path(Path::new(name));
+            let location = EntryLocation::Zip {
+                archive: index,
+                name: name.to_owned(),
+            };
+            if self.insert(&virtual_path, location) {
+                found.push(virtual_path);
+            }
+        }
+
+        self.zip_archives.push(Mutex::new(archive));
+        Ok(found)
+    }
+
+    /// stream a gzipped tar archive once and register all of its files, returning their virtual paths
+    pub fn add_tgz(
+        &mut self,
+        archive_path: &Path,
+    ) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+        let index = self.tar_archives.len();
+        self.tar_archives.push(archive_path.to_path_buf());
+
+        let mut found = Vec::new();
+        let mut archive = open_tgz(archive_path)?;
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            if !entry.header().entry_type().is_file() {
+                continue;
+            }
+            let name = entry.path()?.into_owned();
+            if !is_enclosed(&name) {
+                continue;
+            }
+
+            let mut header = Vec::with_capacity(TAR_HEADER_LEN as usize);
+            (&mut entry).take(TAR_HEADER_LEN).read_to_end(&mut header)?;
+
+            // sidecars are small and needed again for matching, so keep them whole
+            let is_json = name
+                .extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+            let (contents, hashes, embedded_date) = if is_json {
+                let mut contents = header.clone();
+                entry.read_to_end(&mut contents)?;
+                let hashes = hash_reader(&mut contents.as_slice())?;
+                (Some(contents), hashes, None)
+            } else {
+                // this is the only time the file is read before copying, so find its date now too
+                let mut reader = HashingReader::new(Cursor::new(&header).chain(&mut entry));
+                let embedded_date =
+                    embedded_date::read(&mut MediaReader::Stream(&mut reader)).unwrap_or(None);
+                (None, reader.finish()?, embedded_date)
+            };
+
+            let virtual_path = merged_path(&name);
+            let location = EntryLocation::Tar(Box::new(TarEntry {
+                archive: index,
+                contents,
+                header,
+                hashes,
+                embedded_date,
+            }));
+            if self.insert(&virtual_path, location) {
+                found.push(virtual_path);
+            }
+        }
+
+        Ok(found)
+    }
+
+    /// check if a virtual path exists in any of the inputs, without touching the disk
+    pub fn exists(&self, path: &Path) -> bool {
+        self.entries.contains_key(path)
+    }
+
+    /// every virtual directory that holds at least one file
+    pub fn directories(&self) -> impl Iterator<Item = &Path> {
+        self.children.keys().map(PathBuf::as_path)
+    }
+
+    /// all files that sit directly inside the given virtual directory
+    pub fn files_in(&self, dir: &Path) -> &[PathBuf] {
+        self.children.get(dir).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    /// the real on-disk path of a file, if it was not read out of an archive
+    pub fn real_path(&self, path: &Path) -> Option<&Path> {
+        match self.entries.get(path)? {
+            EntryLocation::File(real) => Some(real),
+            EntryLocation::Zip { .. } | EntryLocation::Tar(_) => None,
+        }
+    }
+
+    /// the hashes of a file, if they were already worked out while discovering it
+    pub fn cached_hashes(&self, path: &Path) -> Option<&FileHashes> {
+        match self.entries.get(path)? {
+            EntryLocation::Tar(entry) => Some(&entry.hashes),
+            EntryLocation::File(_) | EntryLocation::Zip { .. } => None,
+        }
+    }
+
+    /// stream the contents of a file through `f`
+    pub fn with_reader<T>(
+        &self,
+        path: &Path,
+        f: impl FnOnce(&mut dyn Read) -> io::Result<T>,
+    ) -> io::Result<T> {
+        let location = self.entries.get(path).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} is not part of the takeout", path.display()),
+            )
+        })?;
+
+        match location {
+            EntryLocation::File(real) => f(&mut File::open(real)?),
+            EntryLocation::Zip { archive, name } => {
+                let mut archive = self.zip_archives[*archive].lock().unwrap();
+                let mut entry = archive.by_name(name)?;
+                f(&mut entry)
+            }
+            EntryLocation::Tar(entry) => match &entry.contents {
+                Some(contents) => f(&mut contents.as_slice()),
+                None => Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    format!(
+                        "{} can only be read while streaming its archive",
+                        path.display()
+                    ),
+                )),
+            },
+        }
+    }
+
+    /// the date a media file was taken according to its own metadata, see `embedded_date`
+    pub fn embedded_date(&self, path: &Path) -> io::Result<Option<EmbeddedDate>> {
+        match self.entries.get(path) {
+            Some(EntryLocation::File(real)) => {
+                embedded_date::read(&mut MediaReader::File(&mut File::open(real)?))
+            }
+            Some(EntryLocation::Tar(entry)) => Ok(entry.embedded_date.clone()),
+            _ => self.with_reader(path, |reader| {
+                embedded_date::read(&mut MediaReader::Stream(reader))
+            }),
+        }
+    }
+
+    pub fn read_to_string(&self, path: &Path) -> io::Result<String> {
+        self.with_reader(path, |reader| {
+            let mut contents = String::new();
+            reader.read_to_string(&mut contents)?;
+            Ok(contents)
+        })
+    }
+
+    /// read at most `len` bytes from the start of a file
+    pub fn read_header(&self, path: &Path, len: u64) -> io::Result<Vec<u8>> {
+        if let Some(EntryLocation::Tar(entry)) = self.entries.get(path) {
+            let len = entry.header.len().min(len as usize);
+            return Ok(entry.header[..len].to_vec());
+        }
+
+        self.with_reader(path, |reader| {
+            let mut header = Vec::with_capacity(len as usize);
+            reader.take(len).read_to_end(&mut header)?;
+            Ok(header)
+        })
+    }
+
+    /// copy a file out of the takeout to a real destination on disk
+    pub fn copy_to(&self, path: &Path, destination: &Path) -> io::Result<u64> {
+        if let Some(real) = self.real_path(path) {
+            return std::fs::copy(real, destination);
+        }
+
+        self.with_reader(path, |reader| {
+            let mut output = File::create(destination)?;
+            io::copy(reader, &mut output)
+        })
+    }
+
+    /// copy many files out of the takeout, given as (virtual path, destination) pairs.
+    ///
+    /// Files inside .tgz archives are batched up so that each archive is only streamed once.
+    pub fn copy_all(&self, files: &[(PathBuf, PathBuf)]) -> io::Result<()> {
+        let mut from_tars: Vec<HashMap<PathBuf, Vec<&Path>>> =
+            vec![HashMap::new(); self.tar_archives.len()];
+        for (path, destination) in files {
+            match self.entries.get(path.as_path()) {
+                Some(EntryLocation::Tar(entry)) => from_tars[entry.archive]
+                    .entry(path.clone())
+                    .or_default()
+                    .push(destination),
+                _ => {
+                    self.copy_to(path, destination)?;
+                }
+            }
+        }
+
+        for (index, mut wanted) in from_tars.into_iter().enumerate() {
+            if wanted.is_empty() {
+                continue;
+            }
+
+            let archive_path = &self.tar_archives[index];
+            let mut archive = open_tgz(archive_path)?;
+            for entry in archive.entries()? {
+                let mut entry = entry?;
+                let virtual_path = merged_path(&entry.path()?);
+                let Some(destinations) = wanted.remove(&virtual_path) else {
+                    continue;
+                };
+
+                let mut output = File::create(destinations[0])?;
+                io::copy(&mut entry, &mut output)?;
+                for destination in &destinations[1..] {
+                    std::fs::copy(destinations[0], destination)?;
+                }
+
+                if wanted.is_empty() {
+                    break;
+                }
+            }
+
+            if let Some(missing) = wanted.keys().next() {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("{} disappeared from its archive", missing.display()),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::Write,
+        path::{Path, PathBuf},
+    };
+
+    use flate2::{write::GzEncoder, Compression};
+    use zip::{write::FileOptions, ZipWriter};
+
+    use super::TakeoutFs;
+
+    fn write_test_zip(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("{}-{}.zip", name, std::process::id()));
+        let mut writer = ZipWriter::new(std::fs::File::create(&path).unwrap());
+        writer
+            .add_directory("Takeout/Google Photos/Trip/", FileOptions::default())
+            .unwrap();
+        writer
+            .start_file(
+                "Takeout/Google Photos/Trip/IMG_0001.jpg",
+                FileOptions::default(),
+            )
+            .unwrap();
+        writer.write_all(b"not really a jpeg").unwrap();
+        writer
+            .start_file(
+                "Takeout/Google Photos/Trip/IMG_0001.jpg.json",
+                FileOptions::default(),
+            )
+            .unwrap();
+        writer.write_all(br#"{"title": "IMG_0001.jpg"}"#).unwrap();
+        writer.finish().unwrap();
+        path
+    }
+
+    #[test]
+    fn test_zip_entries_are_readable() {
+        let zip_path = write_test_zip("takeout-fs-read");
+        let mut takeout = TakeoutFs::default();
+        let found = takeout.add_zip(&zip_path).unwrap();
+
+        let media = PathBuf::from("Takeout/Google Photos/Trip/IMG_0001.jpg");
+        assert_eq!(found.len(), 2);
+        assert!(takeout.exists(&media));
+        assert!(takeout.real_path(&media).is_none());
+        assert_eq!(takeout.files_in(media.parent().unwrap()).len(), 2);
+        assert_eq!(
+            takeout
+                .read_to_string(&media.with_extension("jpg.json"))
+                .unwrap(),
+            r#"{"title": "IMG_0001.jpg"}"#
+        );
+        assert_eq!(takeout.read_header(&media, 8).unwrap(), b"not real");
+
+        std::fs::remove_file(zip_path).unwrap();
+    }
+
+    #[test]
+    fn test_zip_entries_copy_out() {
+        let zip_path = write_test_zip("takeout-fs-copy");
+        let mut takeout = TakeoutFs::default();
+        takeout.add_zip(&zip_path).unwrap();
+
+        let destination = zip_path.with_extension("jpg");
+        let media = PathBuf::from("Takeout/Google Photos/Trip/IMG_0001.jpg");
+        takeout.copy_to(&media, &destination).unwrap();
+        assert_eq!(std::fs::read(&destination).unwrap(), b"not really a jpeg");
+
+        std::fs::remove_file(destination).unwrap();
+        std::fs::remove_file(zip_path).unwrap();
+    }
+
+    fn write_test_tgz(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("{}-{}.tgz", name, std::process::id()));
+        let encoder = GzEncoder::new(std::fs::File::create(&path).unwrap(), Compression::fast());
+        let mut builder = tar::Builder::new(encoder);
+        for (name, contents) in [
+            (
+                "Takeout/Google Photos/Trip/IMG_0001.jpg",
+                &b"not really a jpeg"[..],
+            ),
+            (
+                "Takeout/Google Photos/Trip/IMG_0001.jpg.json",
+                &br#"{"title": "IMG_0001.jpg"}"#[..],
+            ),
+            (
+                "Takeout/Google Photos/Trip/IMG_0002.jpg",
+                &b"another fake jpeg"[..],
+            ),
+        ] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, name, contents).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap();
+        path
+    }
+
+    #[test]
+    fn test_tgz_embedded_dates_are_read_while_hashing() {
+        let jpeg = crate::embedded_date::tests::jpeg(&[(0x9003, "2019:01:01 12:34:56")]);
+        let tgz_path =
+            std::env::temp_dir().join(format!("takeout-fs-dates-{}.tgz", std::process::id()));
+        let encoder = GzEncoder::new(
+            std::fs::File::create(&tgz_path).unwrap(),
+            Compression::fast(),
+        );
+        let mut builder = tar::Builder::new(encoder);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(jpeg.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder
+            .append_data(
+                &mut header,
+                "Takeout/Google Photos/Trip/IMG_0001.jpg",
+                &jpeg[..],
+            )
+            .unwrap();
+        builder.into_inner().unwrap().finish().unwrap();
+
+        let mut takeout = TakeoutFs::default();
+        let found = takeout.add_tgz(&tgz_path).unwrap();
+        let found = takeout.embedded_date(&found[0]).unwrap().unwrap();
+        assert_eq!(found.tag, "EXIF:DateTimeOriginal");
+        assert_eq!(
+            takeout
+                .cached_hashes(Path::new("Takeout/Google Photos/Trip/IMG_0001.jpg"))
+                .unwrap(),
+            &super::hash_reader(&mut jpeg.as_slice()).unwrap()
+        );
+
+        std::fs::remove_file(tgz_path).unwrap();
+    }
+
+    #[test]
+    fn test_tgz_entries_are_cached_and_copied() {
+        let tgz_path = write_test_tgz("takeout-fs-tgz");
+        let mut takeout = TakeoutFs::default();
+        let found = takeout.add_tgz(&tgz_path).unwrap();
+        assert_eq!(found.len(), 3);
+
+        let first = PathBuf::from("Takeout/Google Photos/Trip/IMG_0001.jpg");
+        let second = PathBuf::from("Takeout/Google Photos/Trip/IMG_0002.jpg");
+        assert_eq!(
+            takeout
+                .read_to_string(&first.with_extension("jpg.json"))
+                .unwrap(),
+            r#"{"title": "IMG_0001.jpg"}"#
+        );
+        assert!(takeout.read_to_string(&first).is_err());
+        assert_eq!(takeout.read_header(&first, 8).unwrap(), b"not real");
+        let hashes = takeout.cached_hashes(&first).unwrap();
+        assert_eq!(
+            *hashes,
+            super::hash_reader(&mut &b"not really a jpeg"[..]).unwrap()
+        );
+        // what immich would have as the checksum of the same file
+        assert_eq!(hashes.sha1, "5a75328649a4df2eb64f0e10f6912f612094e8f4");
+
+        let first_out = tgz_path.with_extension("1.jpg");
+        let second_out = tgz_path.with_extension("2.jpg");
+        takeout
+            .copy_all(&[
+                (second.clone(), second_out.clone()),
+                (first.clone(), first_out.clone()),
+            ])
+            .unwrap();
+        assert_eq!(std::fs::read(&first_out).unwrap(), b"not really a jpeg");
+        assert_eq!(std::fs::read(&second_out).unwrap(), b"another fake jpeg");
+
+        std::fs::remove_file(first_out).unwrap();
+        std::fs::remove_file(second_out).unwrap();
+        std::fs::remove_file(tgz_path).unwrap();
+    }
+}
@@ -0,0 +1,217 @@
+//! Google cuts long names short when it builds a takeout, and does so differently for media files and sidecars:
+//!
+//! - a media file keeps its extension, and its stem is cut so the whole name is at most 47 characters,
+//!   e.g. `Screenshot_2018-08-24-14-58-25-149_com.snapchat.android.jpg` -> `..._com.snap.jpg`
+//! - a sidecar name is cut to 46 characters, counting from the start of the original file name, so the
+//!   extension is usually lost, e.g. `..._com.snapcha.json`, `...family!.p.json`
+//!
+//! In both cases the `(n)` of a duplicate is added after cutting, and `.supplemental-metadata` is treated as part
+//! of the name, so it is cut off before anything else.
+
+use crate::sidecar::SUPPLEMENTAL_METADATA;
+
+/// sidecar names are cut to this many characters, before `(n)` and `.json` are added
+pub const SIDECAR_NAME_CHARS: usize = 46;
+
+/// media names are cut to this many characters including the extension, before `(n)` is added
+pub const MEDIA_NAME_CHARS: usize = 47;
+
+/// cut a name to at most `max_chars` characters, counting characters rather than bytes like google does
+pub fn truncate(name: &str, max_chars: usize) -> &str {
+    match name.char_indices().nth(max_chars) {
+        Some((end, _)) => &name[..end],
+        None => name,
+    }
+}
+
+/// The file name google gives the sidecar of a media file, from the name the media file was uploaded with
+pub fn sidecar_name(original: &str, supplemental: bool, counter: Option<u32>) -> String {
+    let full = match supplemental {
+        true => format!("{}.{}", original, SUPPLEMENTAL_METADATA),
+        false => original.to_owned(),
+    };
+
+    let mut name = truncate(&full, SIDECAR_NAME_CHARS).to_owned();
+    if let Some(counter) = counter {
+        name.push_str(&format!("({})", counter));
+    }
+    name.push_str(".json");
+    name
+}
+
+/// If a media file name is as long as google allows, its stem may have been cut short and the original name
+/// can't be known. Its sidecar will then be named with the stem followed by some of the characters that were cut.
+pub fn is_truncated_media_name(stem: &str, ext: &str) -> bool {
+    stem.chars().count() + 1 + ext.chars().count() >= MEDIA_NAME_CHARS
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::{is_truncated_media_name, sidecar_name, truncate};
+    use crate::{sidecar::SidecarIndex, split_duplicate_counter};
+
+    /// (name the file was uploaded with, duplicate counter, supplemental sidecar, media file in the takeout,
+    /// sidecar in the takeout)
+    const EXPORTS: &[(&str, Option<u32>, bool, &str, &str)] = &[
+        (
+            "Screenshot_2018-08-24-14-58-25-149_com.snapchat.android.jpg",
+            None,
+            false,
+            "Screenshot_2018-08-24-14-58-25-149_com.snap.jpg",
+            "Screenshot_2018-08-24-14-58-25-149_com.snapcha.json",
+        ),
+        (
+            "Screenshot_2018-08-24-14-58-25-149_com.snapchat.android.jpg",
+            Some(1),
+            false,
+            "Screenshot_2018-08-24-14-58-25-149_com.snap(1).jpg",
+            "Screenshot_2018-08-24-14-58-25-149_com.snapcha(1).json",
+        ),
+        (
+            "FB_IMG_1552945417291_some_really_long_descriptive_name.jpg",
+            None,
+            false,
+            "FB_IMG_1552945417291_some_really_long_descr.jpg",
+            "FB_IMG_1552945417291_some_really_long_descript.json",
+        ),
+        (
+            "IMG-20190204-WA0007 - Holiday in Rome with friends.jpeg",
+            Some(2),
+            false,
+            "IMG-20190204-WA0007 - Holiday in Rome with(2).jpeg",
+            "IMG-20190204-WA0007 - Holiday in Rome with fri(2).json",
+        ),
+        (
+            "Zdjęcie z wakacji nad morzem w Międzyzdrojach 2019.jpg",
+            None,
+            false,
+            "Zdjęcie z wakacji nad morzem w Międzyzdroja.jpg",
+            "Zdjęcie z wakacji nad morzem w Międzyzdrojach .json",
+        ),
+        (
+            "original_4b1e0c3a-8f2d-4a1b-9c3e-7d6f5a4b3c2d_P.jpg",
+            None,
+            false,
+            "original_4b1e0c3a-8f2d-4a1b-9c3e-7d6f5a4b3c.jpg",
+            "original_4b1e0c3a-8f2d-4a1b-9c3e-7d6f5a4b3c2d_.json",
+        ),
+        // one character too long, so the media file only loses the `!` but the sidecar keeps it
+        (
+            "2019-07-04 Beach trip with the whole family!.png",
+            None,
+            false,
+            "2019-07-04 Beach trip with the whole family.png",
+            "2019-07-04 Beach trip with the whole family!.p.json",
+        ),
+        // exactly 47 characters, so the media file is untouched and only the sidecar is cut
+        (
+            "Photo taken at the summit of Mt Taranaki 12.jpg",
+            None,
+            false,
+            "Photo taken at the summit of Mt Taranaki 12.jpg",
+            "Photo taken at the summit of Mt Taranaki 12.jp.json",
+        ),
+        (
+            "Photo taken at the summit of Mt Taranaki 1.jpg",
+            None,
+            false,
+            "Photo taken at the summit of Mt Taranaki 1.jpg",
+            "Photo taken at the summit of Mt Taranaki 1.jpg.json",
+        ),
+        // newer takeouts, where `.supplemental-metadata` is cut before the name itself
+        (
+            "Photo taken at the summit of Mt Taranaki.jpg",
+            None,
+            true,
+            "Photo taken at the summit of Mt Taranaki.jpg",
+            "Photo taken at the summit of Mt Taranaki.jpg.s.json",
+        ),
+        (
+            "Photo taken at the summit of Mt Taranak.jpg",
+            Some(3),
+            true,
+            "Photo taken at the summit of Mt Taranak(3).jpg",
+            "Photo taken at the summit of Mt Taranak.jpg.su(3).json",
+        ),
+        (
+            "Screenshot_2018-08-24-14-58-25-149_com.snapchat.android.jpg",
+            Some(1),
+            true,
+            "Screenshot_2018-08-24-14-58-25-149_com.snap(1).jpg",
+            "Screenshot_2018-08-24-14-58-25-149_com.snapcha(1).json",
+        ),
+    ];
+
+    /// sidecars which must not be picked for the media file, even though they look close
+    const MISMATCHES: &[(&str, &str)] = &[
+        // a different duplicate
+        (
+            "Screenshot_2018-08-24-14-58-25-149_com.snap(1).jpg",
+            "Screenshot_2018-08-24-14-58-25-149_com.snapcha(2).json",
+        ),
+        // the media name isn't long enough to have been cut, so the sidecar belongs to a longer name
+        (
+            "Photo taken at the summit of Mt.jpg",
+            "Photo taken at the summit of Mt Taranaki 12.jp.json",
+        ),
+        // the sidecar was cut at 46 characters, but doesn't start with the media stem
+        (
+            "Screenshot_2018-08-24-14-58-25-149_com.snap.jpg",
+            "Screenshot_2018-08-24-14-58-25-149_com.instagr.json",
+        ),
+    ];
+
+    #[test]
+    fn test_sidecar_names_follow_the_truncation_rule() {
+        for (original, counter, supplemental, _, sidecar) in EXPORTS {
+            assert_eq!(
+                sidecar_name(original, *supplemental, *counter),
+                *sidecar,
+                "sidecar of {}",
+                original
+            );
+        }
+    }
+
+    #[test]
+    fn test_truncated_media_names_are_detected() {
+        for (original, _, _, media, _) in EXPORTS {
+            let (stem, ext) = media.rsplit_once('.').unwrap();
+            let stem = split_duplicate_counter(stem).map_or(stem, |(stem, _)| stem);
+            if !original.starts_with(stem) || original.len() > stem.len() + 1 + ext.len() {
+                assert!(is_truncated_media_name(stem, ext), "{}", media);
+            }
+        }
+        assert!(!is_truncated_media_name("IMG_0001", "jpg"));
+        assert_eq!(truncate("Międzyzdroje", 3), "Mię");
+    }
+
+    #[test]
+    fn test_resolve_truncated_names() {
+        for (_, _, _, media, sidecar) in EXPORTS {
+            let mut index = SidecarIndex::default();
+            index.insert_name(&Path::new("/takeout/Album").join(sidecar));
+            assert_eq!(
+                index.resolve(&Path::new("/takeout/Album").join(media)),
+                Some(&PathBuf::from("/takeout/Album").join(sidecar)),
+                "{} should match {}",
+                media,
+                sidecar
+            );
+        }
+
+        for (media, sidecar) in MISMATCHES {
+            let mut index = SidecarIndex::default();
+            index.insert_name(&Path::new("/takeout/Album").join(sidecar));
+            assert_eq!(
+                index.resolve(&Path::new("/takeout/Album").join(media)),
+                None,
+                "{} should not match {}",
+                media,
+                sidecar
+            );
+        }
+    }
+}